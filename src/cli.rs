@@ -0,0 +1,294 @@
+use crate::events::EventFormat;
+use crate::metronome::data::beat::accent::MetronomeBeatAccent;
+use crate::metronome::data::{
+    SUBDIVISION_RANGE, TEMPO_RANGE, TempoType, TimeSignature, beat::accent::AccentPattern,
+    dropout::Dropout, gap_click::GapClick, polyrhythm::PolyrhythmLayer,
+    subdivision_setting::SubdivisionSetting, volume::VOLUME_RANGE,
+};
+use crate::metronome::sound::{SoundSource, synth::SynthClick};
+use crate::timer::parse_duration;
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    time::Duration,
+};
+
+/// A metronome written in Rust. Once entered, you can type in commands to change the
+/// various settings within the metronome, such as the tempo, the time signature, the
+/// subdivision, etc. Once entered the metronome, type in `help` and press enter to see
+/// more detailed information about all the commands
+#[derive(Parser, Clone, Debug)]
+#[command(version, about, long_about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(flatten)]
+    pub metronome_args: MetronomeArgs,
+
+    /// A practice program to play through, which is a TOML file listing sections of
+    /// bars, each with its own tempo, time signature, subdivision, etc. A `.mid` file
+    /// plays its tempo and time signature map instead, and a `.musicxml` score plays
+    /// its measures
+    #[arg(short, long)]
+    pub program: Option<PathBuf>,
+
+    /// Control the metronome with single keys instead of typed commands. Space pauses,
+    /// +/- and the arrow keys change the tempo, number keys set the subdivision, `t`
+    /// taps and `:` opens the command line
+    #[arg(short, long)]
+    pub keys: bool,
+
+    /// A TOML file that changes which key does what, with a `[keys]` table like
+    /// `space = "toggle"` or `up = "tempo +10"`. Turns on `--keys`
+    #[arg(long, value_name = "FILE")]
+    pub keymap: Option<PathBuf>,
+
+    /// Listen for OSC messages on this local UDP port. Messages like
+    /// `/metronome/tempo 120` or `/metronome/pause` run the command of the same name,
+    /// and everyone who sent a message in the last minute gets `/metronome/beat` on
+    /// every beat
+    #[arg(long, value_name = "PORT")]
+    pub osc: Option<u16>,
+
+    /// Also send `/metronome/beat` messages to this address, like `127.0.0.1:9000`.
+    /// Can be given more than once
+    #[arg(long, value_name = "ADDRESS", requires = "osc")]
+    pub osc_send: Vec<SocketAddr>,
+
+    /// Serve a control page on this port, which shows the settings and the beats and
+    /// can change them from a browser
+    #[arg(long, value_name = "PORT")]
+    pub web: Option<u16>,
+
+    /// The address the control page is served on. Use `0.0.0.0` to reach it from
+    /// other devices on the network, like a tablet
+    #[arg(long, value_name = "IP", default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST), requires = "web")]
+    pub web_address: IpAddr,
+
+    /// Another name the control page can be opened by, like `studio-mac.local`.
+    /// Otherwise only `localhost` and IP addresses work, so other websites can't reach
+    /// it by pointing their own name at this computer
+    #[arg(long, value_name = "NAME", requires = "web")]
+    pub web_host: Option<String>,
+
+    /// Write every scheduled click and every change of the tempo, time signature,
+    /// subdivision or pause to stdout, one event per line, for other programs to
+    /// follow. The screen moves to stderr, so it still shows when stdout is piped
+    #[arg(long, value_name = "FORMAT")]
+    pub events: Option<EventFormat>,
+
+    /// Write the events to this file instead of stdout
+    #[arg(long, value_name = "FILE", requires = "events")]
+    pub events_file: Option<PathBuf>,
+
+    /// Run in the background without a screen or typed commands, taking commands from
+    /// `metronome ctl` instead
+    #[arg(long, conflicts_with_all = ["keys", "keymap"])]
+    pub daemon: bool,
+
+    /// The Unix socket the daemon listens on. Defaults to `metronome.sock` in
+    /// `$XDG_RUNTIME_DIR`, or in the temporary directory if it isn't set
+    #[arg(long, value_name = "PATH", requires = "daemon")]
+    pub socket: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub sound_args: SoundArgs,
+
+    /// A sound file, or `"synth <WAVEFORM> ..."`, to play for the timer alarm instead
+    /// of the built-in one
+    #[arg(long, value_name = "SOUND")]
+    pub alarm_sound: Option<SoundSource>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// The settings the metronome starts with
+#[derive(Args, Clone, Debug)]
+pub struct MetronomeArgs {
+    /// The tempo for the metronome, in beats per minute. Cannot be less than 10,
+    /// or greater than 400
+    #[arg(default_value_t = 60, value_parser = clap::value_parser!(i32).range(TEMPO_RANGE))]
+    pub tempo: i32,
+
+    /// The time signature for the metronome, in the format of a fraction. For example,
+    /// `4/4` or `6/8`. Additive time signatures like `2+2+3/8` set how the beats are
+    /// grouped
+    #[arg(default_value_t = TimeSignature::default())]
+    pub time_signature: TimeSignature,
+
+    /// The tempo type for the metronome. By default, it's quarter note equals,
+    /// but for time signatures like `6/8`, it'll be dotted quarter equals, and
+    /// for time signatures like `2/2`, it'll be half-note equals.
+    #[arg(value_enum, short, long)]
+    pub tempo_type: Option<TempoType>,
+
+    /// The subdivision for the metronome, in terms of numbers. For example,
+    /// `2` represents splitting a beat into 2
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(i32).range(SUBDIVISION_RANGE))]
+    pub subdivision: i32,
+
+    /// Which subdivided beat to play. "x" represents play and "-" represents silent.
+    /// For example, `-x` with a subdivision of 2 will only play the offbeat
+    #[arg(long, allow_hyphen_values = true)]
+    pub subdivision_setting: Option<SubdivisionSetting>,
+
+    /// A custom accent for every beat in the measure. "X" is accented, "x" is a
+    /// normal beat and "." is played like a subdivision. For example, `X.x.xx.` for
+    /// `7/8`
+    #[arg(short, long)]
+    pub accents: Option<AccentPattern>,
+
+    /// A polyrhythm layer played on top of the metronome, with the format of
+    /// `<PULSES> [beat | bar] [pan]`. For example, `"3 bar"` in `2/4` plays 3 against 2,
+    /// and `pan` puts each layer on its own side
+    #[arg(long, value_name = "LAYER")]
+    pub poly: Option<PolyrhythmLayer>,
+
+    /// Gap-click training, with the format of `<PLAY BARS> <MUTE BARS>`. For example,
+    /// `"4 2"` plays the click for 4 bars, then mutes it for 2 while the beats go on
+    #[arg(long, value_name = "BARS")]
+    pub gap: Option<GapClick>,
+
+    /// Mutes each click at random, with the format of
+    /// `<PERCENT> [keep-downbeats] [seed <SEED>]`. The same seed drops the same beats
+    #[arg(long, value_name = "DROPOUT")]
+    pub dropout: Option<Dropout>,
+
+    /// The master volume, in percent from 0 to 100
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(VOLUME_RANGE))]
+    pub volume: u8,
+
+    /// The volume of accented beats, in percent from 0 to 100
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(VOLUME_RANGE))]
+    pub accented_volume: u8,
+
+    /// The volume of normal beats, in percent from 0 to 100
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(VOLUME_RANGE))]
+    pub beat_volume: u8,
+
+    /// The volume of subdivided beats, in percent from 0 to 100
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(VOLUME_RANGE))]
+    pub subdivision_volume: u8,
+
+    /// The volume of the polyrhythm layer, in percent from 0 to 100
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(VOLUME_RANGE))]
+    pub poly_volume: u8,
+}
+
+/// Sounds to click with instead of the built-in sounds. Each one is either a WAV, OGG,
+/// FLAC or MP3 file, or a synthesized click written as `"synth <WAVEFORM> [PITCH]
+/// [DECAY] [LENGTH]"`, like `"synth sine 1500 20 60"`
+#[derive(Args, Clone, Debug)]
+pub struct SoundArgs {
+    /// The sound to play for accented beats
+    #[arg(long, value_name = "SOUND")]
+    pub accented_sound: Option<SoundSource>,
+
+    /// The sound to play for normal beats
+    #[arg(long, value_name = "SOUND")]
+    pub beat_sound: Option<SoundSource>,
+
+    /// The sound to play for subdivided beats
+    #[arg(long, value_name = "SOUND")]
+    pub subdivision_sound: Option<SoundSource>,
+
+    /// The sound to play for the pulses of the polyrhythm layer
+    #[arg(long, value_name = "SOUND")]
+    pub poly_sound: Option<SoundSource>,
+
+    /// Synthesize every click that isn't given its own sound, instead of playing the
+    /// built-in samples
+    #[arg(long)]
+    pub synth: bool,
+}
+
+impl SoundArgs {
+    /// Every sound that replaces a built-in sound, along with the accent it's played for
+    pub fn sound_sources(&self) -> Vec<(MetronomeBeatAccent, SoundSource)> {
+        [
+            (MetronomeBeatAccent::Accented, &self.accented_sound),
+            (MetronomeBeatAccent::Beat, &self.beat_sound),
+            (MetronomeBeatAccent::Subdivision, &self.subdivision_sound),
+            (MetronomeBeatAccent::Polyrhythm, &self.poly_sound),
+        ]
+        .into_iter()
+        .filter_map(|(accent, sound_source)| match sound_source {
+            Some(sound_source) => Some((accent, sound_source.clone())),
+            None if self.synth => {
+                Some((accent, SoundSource::Synth(SynthClick::default_for(accent))))
+            }
+            None => None,
+        })
+        .collect()
+    }
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Render a click track to a WAV file instead of playing it
+    Render(RenderArgs),
+    /// Write the click map to a Standard MIDI File, with its tempo and time signature
+    /// changes, for notation software and DAWs
+    Midi(MidiArgs),
+    /// Send a command to a metronome running with `--daemon`, like
+    /// `metronome ctl tempo +10`, and print its settings afterwards
+    Ctl(CtlArgs),
+}
+
+#[derive(Args, Clone, Debug)]
+#[command(group(ArgGroup::new("length").required(true).args(["bars", "duration"])))]
+pub struct RenderArgs {
+    /// The WAV file to write the click track to
+    pub output: PathBuf,
+
+    #[command(flatten)]
+    pub metronome_args: MetronomeArgs,
+
+    #[command(flatten)]
+    pub sound_args: SoundArgs,
+
+    /// The number of bars to render
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub bars: Option<u32>,
+
+    /// How long the click track is, with the format of `HH:MM:SS`, where hours are
+    /// optional
+    #[arg(short, long, value_parser = parse_duration)]
+    pub duration: Option<Duration>,
+}
+
+#[derive(Args, Clone, Debug)]
+#[command(group(ArgGroup::new("length").required(true).args(["bars", "duration"])))]
+pub struct MidiArgs {
+    /// The MIDI file to write the click map to
+    pub output: PathBuf,
+
+    #[command(flatten)]
+    pub metronome_args: MetronomeArgs,
+
+    /// A practice program to write out, which is played from its first section until
+    /// it finishes or the length runs out
+    #[arg(short, long)]
+    pub program: Option<PathBuf>,
+
+    /// The number of bars to write
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub bars: Option<u32>,
+
+    /// How long the click map is, with the format of `HH:MM:SS`, where hours are
+    /// optional
+    #[arg(short, long, value_parser = parse_duration)]
+    pub duration: Option<Duration>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct CtlArgs {
+    /// The Unix socket of the daemon, if it was started with `--socket`
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+
+    /// The command to run, written the same way as in the metronome. Without one, the
+    /// settings are printed without changing anything
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub command: Vec<String>,
+}
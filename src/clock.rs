@@ -0,0 +1,92 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Where the metronome gets the current time from. Everything that keeps time asks
+/// its clock instead of calling `Instant::now()`, so a [`ManualClock`] can stand in
+/// for the real one and minutes of playing can be checked without waiting for them
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// A clock shared by everything that keeps time for the same metronome
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The real time, from the monotonic system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl SystemClock {
+    pub fn shared() -> SharedClock {
+        Arc::new(Self)
+    }
+}
+
+/// A clock that only moves when it's told to. Clones share the same time
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    /// A clock that starts at the current time, and stays there until it's moved
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+
+    /// Moves the clock to `instant`. A clock never goes backwards, so an earlier
+    /// instant leaves it where it is
+    pub fn set(&self, instant: Instant) {
+        let mut now = self.now.lock().unwrap();
+        *now = (*now).max(instant);
+    }
+
+    pub fn shared(&self) -> SharedClock {
+        Arc::new(self.clone())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{Clock, ManualClock};
+    use std::time::Duration;
+
+    #[test]
+    fn manual_clock_only_moves_forward() {
+        let clock = ManualClock::new();
+        let shared_clock = clock.shared();
+        let start = clock.now();
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(shared_clock.now() - start, Duration::from_secs(90));
+
+        clock.set(start + Duration::from_secs(30));
+        assert_eq!(shared_clock.now() - start, Duration::from_secs(90));
+    }
+}
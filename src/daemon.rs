@@ -0,0 +1,243 @@
+use anyhow::{Context, anyhow};
+use std::{
+    env, fs,
+    io::{BufRead, Read, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+#[cfg(unix)]
+use std::{
+    io::BufReader,
+    net::Shutdown,
+    os::unix::net::{UnixListener, UnixStream},
+    thread,
+    time::Duration,
+};
+
+use metronome::{UserInput, cli::CtlArgs};
+
+#[cfg(unix)]
+use crate::ui::screen;
+
+const SOCKET_FILE_NAME: &str = "metronome.sock";
+/// How long `metronome ctl` has to send its command
+#[cfg(unix)]
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the daemon listens if no socket is given. The temporary directory is shared,
+/// so the user id keeps each user's metronome apart there
+pub fn default_socket_path() -> PathBuf {
+    if let Some(runtime_dir) = env::var_os("XDG_RUNTIME_DIR") {
+        return PathBuf::from(runtime_dir).join(SOCKET_FILE_NAME);
+    }
+
+    #[cfg(unix)]
+    {
+        // SAFETY: `getuid` only reads the user id of the process and can't fail
+        let uid = unsafe { libc::getuid() };
+        env::temp_dir().join(format!("metronome-{}.sock", uid))
+    }
+
+    #[cfg(not(unix))]
+    env::temp_dir().join(SOCKET_FILE_NAME)
+}
+
+/// Reads one command from a `metronome ctl` connection, sends it on, and answers with
+/// the settings once it's been handled
+fn answer_command(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    sender: &mpsc::Sender<UserInput>,
+) -> anyhow::Result<()> {
+    let mut command = String::new();
+    reader.take(1024).read_line(&mut command)?;
+    let command = command.trim();
+
+    // Without a command, only the settings are sent back
+    if !command.is_empty() {
+        match command.parse::<UserInput>()? {
+            UserInput::Unknown(command) => {
+                writeln!(writer, "Unknown command `{}`!", command)?;
+                return Ok(());
+            }
+            // The metronome stops without answering, so this is answered first
+            UserInput::Quit => {
+                writeln!(writer, "Goodbye!")?;
+                writer.flush()?;
+                sender.send(UserInput::Quit)?;
+                return Ok(());
+            }
+            user_input => sender.send(user_input)?,
+        }
+    }
+
+    let (status_sender, status_receiver) = mpsc::channel();
+    sender.send(UserInput::SendStatus(status_sender))?;
+
+    // The metronome is gone if it quit before answering
+    match status_receiver.recv() {
+        Ok(status) => write!(writer, "{}", status)?,
+        Err(_) => writeln!(writer, "Goodbye!")?,
+    }
+
+    Ok(())
+}
+
+/// The socket of a running daemon, which is removed again once this is dropped
+pub struct ControlServer {
+    socket_path: PathBuf,
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket_path);
+    }
+}
+
+/// Listens on a Unix socket for commands from `metronome ctl`, one per connection
+#[cfg(unix)]
+pub fn start_control_server(
+    socket_path: &Path,
+    sender: mpsc::Sender<UserInput>,
+) -> anyhow::Result<ControlServer> {
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(anyhow!(
+                "A metronome daemon is already listening on {}!",
+                socket_path.display()
+            ));
+        }
+
+        // Left behind by a daemon that didn't get to clean up
+        fs::remove_file(socket_path)?;
+    }
+
+    let listener = UnixListener::bind(socket_path)?;
+    let control_server = ControlServer {
+        socket_path: socket_path.to_path_buf(),
+    };
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let sender = sender.clone();
+
+            // Each connection is answered on its own thread, and one that never sends
+            // its command is given up on, so it can't keep the others waiting
+            thread::spawn(move || -> anyhow::Result<()> {
+                stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+                let reader = stream.try_clone()?;
+
+                // A client that hangs up early shouldn't stop the daemon
+                if let Err(err) = answer_command(BufReader::new(reader), &stream, &sender) {
+                    writeln!(screen(), "{:#}", err)?;
+                }
+
+                Ok(())
+            });
+        }
+    });
+
+    Ok(control_server)
+}
+
+#[cfg(not(unix))]
+pub fn start_control_server(
+    _socket_path: &Path,
+    _sender: mpsc::Sender<UserInput>,
+) -> anyhow::Result<ControlServer> {
+    Err(anyhow!(
+        "Daemon mode needs Unix sockets, which this system doesn't have!"
+    ))
+}
+
+/// Sends the command of `metronome ctl` to the daemon and prints its answer
+#[cfg(unix)]
+pub fn send_command(ctl_args: &CtlArgs) -> anyhow::Result<()> {
+    let socket_path = ctl_args.socket.clone().unwrap_or_else(default_socket_path);
+
+    let mut stream = UnixStream::connect(&socket_path).with_context(|| {
+        format!(
+            "No metronome daemon is listening on {}. Start one with `metronome --daemon`",
+            socket_path.display()
+        )
+    })?;
+
+    writeln!(stream, "{}", ctl_args.command.join(" "))?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer)?;
+    print!("{}", answer);
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn send_command(_ctl_args: &CtlArgs) -> anyhow::Result<()> {
+    Err(anyhow!(
+        "Daemon mode needs Unix sockets, which this system doesn't have!"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::daemon::answer_command;
+    use metronome::UserInput;
+    use std::{io::BufReader, sync::mpsc, thread};
+
+    /// Answers `command` like the daemon would, with a metronome that reports `status`
+    fn answer(command: &str, status: &'static str) -> (String, Vec<String>) {
+        let (sender, receiver) = mpsc::channel();
+
+        let metronome = thread::spawn(move || {
+            let mut handled = Vec::new();
+
+            for user_input in receiver {
+                match user_input {
+                    UserInput::SendStatus(status_sender) => {
+                        status_sender.send(status.to_string()).unwrap()
+                    }
+                    user_input => handled.push(format!("{:?}", user_input)),
+                }
+            }
+
+            handled
+        });
+
+        let mut answer = Vec::new();
+        answer_command(BufReader::new(command.as_bytes()), &mut answer, &sender).unwrap();
+        drop(sender);
+
+        (
+            String::from_utf8(answer).unwrap(),
+            metronome.join().unwrap(),
+        )
+    }
+
+    #[test]
+    fn commands_are_answered_with_the_status() {
+        assert_eq!(
+            answer("tempo +10\n", "Tempo = 130\n"),
+            (
+                "Tempo = 130\n".to_string(),
+                vec!["NudgeTempo(10)".to_string()]
+            )
+        );
+        assert_eq!(
+            answer("\n", "PAUSED\n"),
+            ("PAUSED\n".to_string(), Vec::new())
+        );
+        assert_eq!(
+            answer("quit\n", ""),
+            ("Goodbye!\n".to_string(), vec!["Quit".to_string()])
+        );
+        assert_eq!(
+            answer("jump\n", ""),
+            ("Unknown command `jump`!\n".to_string(), Vec::new())
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    time::Instant,
+};
+
+use crate::metronome::data::{
+    MetronomeData,
+    beat::accent::{MetronomeBeatAccent, get_metronome_beat_accent},
+};
+
+/// How events are written
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum EventFormat {
+    /// One JSON object per line
+    Json,
+}
+
+/// What silenced a click
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum MutedBy {
+    GapClick,
+    SubdivisionSetting,
+    Dropout,
+}
+
+/// The settings that are logged whenever one of them changes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct State {
+    tempo: i32,
+    tempo_type: String,
+    time_signature: String,
+    subdivision: i32,
+    is_paused: bool,
+}
+
+impl State {
+    fn of(metronome_data: &MetronomeData) -> Self {
+        Self {
+            tempo: metronome_data.tempo(),
+            tempo_type: metronome_data.tempo_type().to_string(),
+            time_signature: metronome_data.time_signature().notation(),
+            subdivision: metronome_data.subdivision(),
+            is_paused: metronome_data.is_paused,
+        }
+    }
+}
+
+/// A line of the event log. Times are in milliseconds since the metronome started, on
+/// a clock that never goes backwards
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event<'a> {
+    Click {
+        time_ms: f64,
+        bar: u32,
+        beat: i32,
+        subdivided_beat: i32,
+        accent: MetronomeBeatAccent,
+        muted_by: Option<MutedBy>,
+    },
+    State {
+        time_ms: f64,
+        #[serde(flatten)]
+        state: &'a State,
+    },
+}
+
+/// Writes every scheduled click and every change of the settings as they happen, so
+/// other programs can follow the metronome
+pub struct EventLog {
+    writer: Box<dyn Write>,
+    start: Instant,
+    /// The number of bars started so far
+    bars_started: u32,
+    last_state: Option<State>,
+}
+
+impl EventLog {
+    /// Writes events to `path`, or to stdout if there's no path, in which case
+    /// anything else has to be printed somewhere else, like stderr
+    pub fn open(path: Option<&Path>, start: Instant) -> io::Result<Self> {
+        let writer: Box<dyn Write> = match path {
+            Some(path) => Box::new(File::create(path)?),
+            None => Box::new(io::stdout()),
+        };
+
+        Ok(Self::new(writer, start))
+    }
+
+    fn new(writer: Box<dyn Write>, start: Instant) -> Self {
+        Self {
+            writer,
+            start,
+            bars_started: 0,
+            last_state: None,
+        }
+    }
+
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.flush()
+    }
+
+    fn time_ms(&self, timestamp: Instant) -> f64 {
+        timestamp
+            .saturating_duration_since(self.start)
+            .as_secs_f64()
+            * 1000.0
+    }
+
+    /// Logs the click of the current subdivided beat, scheduled for `timestamp`, even if
+    /// it's silenced
+    pub fn log_click(
+        &mut self,
+        timestamp: Instant,
+        metronome_data: &MetronomeData,
+    ) -> io::Result<()> {
+        let beat_info = metronome_data.beat_info;
+        if beat_info == (0, 0) {
+            self.bars_started += 1;
+        }
+
+        let muted_by = if metronome_data.is_muted() {
+            Some(MutedBy::GapClick)
+        } else if !metronome_data.is_played_by_subdivision_setting() {
+            Some(MutedBy::SubdivisionSetting)
+        } else if metronome_data.is_dropped() {
+            Some(MutedBy::Dropout)
+        } else {
+            None
+        };
+
+        self.write_event(&Event::Click {
+            time_ms: self.time_ms(timestamp),
+            bar: self.bars_started,
+            beat: beat_info.current_beat,
+            subdivided_beat: beat_info.subdivided_beat,
+            accent: get_metronome_beat_accent(metronome_data.beat_accents(), beat_info),
+            muted_by,
+        })
+    }
+
+    /// Logs a pulse of the polyrhythm, scheduled for `timestamp`
+    pub fn log_polyrhythm_pulse(
+        &mut self,
+        timestamp: Instant,
+        metronome_data: &MetronomeData,
+    ) -> io::Result<()> {
+        let beat_info = metronome_data.beat_info;
+
+        self.write_event(&Event::Click {
+            time_ms: self.time_ms(timestamp),
+            bar: self.bars_started,
+            beat: beat_info.current_beat,
+            subdivided_beat: beat_info.subdivided_beat,
+            accent: MetronomeBeatAccent::Polyrhythm,
+            muted_by: metronome_data.is_muted().then_some(MutedBy::GapClick),
+        })
+    }
+
+    /// Logs the settings if they changed since they were last logged
+    pub fn log_state(&mut self, metronome_data: &MetronomeData) -> io::Result<()> {
+        let state = State::of(metronome_data);
+        if self.last_state.as_ref() == Some(&state) {
+            return Ok(());
+        }
+
+        self.write_event(&Event::State {
+            time_ms: self.time_ms(metronome_data.clock().now()),
+            state: &state,
+        })?;
+        self.last_state = Some(state);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::Cli;
+    use crate::events::EventLog;
+    use crate::metronome::data::MetronomeData;
+    use clap::Parser;
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    /// Keeps everything written to it, so a test can read it back
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn clicks_and_changes_are_logged() {
+        let cli = Cli::parse_from([
+            "metronome",
+            "120",
+            "2/4",
+            "-s",
+            "2",
+            "--subdivision-setting",
+            "x-",
+        ]);
+        let mut data = MetronomeData::new(&cli.metronome_args).unwrap();
+
+        let buffer = SharedBuffer::default();
+        let start = Instant::now();
+        let mut event_log = EventLog::new(Box::new(buffer.clone()), start);
+
+        event_log.log_state(&data).unwrap();
+        for beat in 0..3 {
+            data.move_to_next_subdivided_beat();
+            event_log
+                .log_click(start + Duration::from_millis(250 * beat), &data)
+                .unwrap();
+        }
+        // Only changes are logged
+        event_log.log_state(&data).unwrap();
+
+        let log = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with(r#"{"type":"state","time_ms":"#));
+        assert!(lines[0].ends_with(
+            r#""tempo":120,"tempo_type":"Quarter Note","time_signature":"2/4","subdivision":2,"is_paused":false}"#
+        ));
+        assert_eq!(
+            lines[1..],
+            [
+                r#"{"type":"click","time_ms":0.0,"bar":1,"beat":0,"subdivided_beat":0,"accent":"accented","muted_by":null}"#,
+                r#"{"type":"click","time_ms":250.0,"bar":1,"beat":0,"subdivided_beat":1,"accent":"subdivision","muted_by":"subdivision_setting"}"#,
+                r#"{"type":"click","time_ms":500.0,"bar":1,"beat":1,"subdivided_beat":0,"accent":"beat","muted_by":null}"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,72 @@
+/// The commands that can be typed into the metronome, one per line
+pub const HELP_LINES: &[&str] = &[
+    "Commands: ",
+    "pause, p: Pause the metronome",
+    "resume, r: Resume the metronome",
+    "toggle: Pause the metronome if it's playing, or resume it if it's paused",
+    "quit, q: Exit the metronome",
+    "help, h: Print help",
+    "clear, c: Clear the screen",
+    "tempo, t <TEMPO>: Set the tempo of the metronome",
+    "\tExample: `t 60`, or `t +5` to make the current tempo 5 faster",
+    "time <TIME_SIGNATURE>: Set the time signature of the metronome",
+    "\tExample: `time 6/8`, or `time 2+2+3/8` to choose how the beats are grouped",
+    "tempo-type, tt <TEMPO_TYPE>: Set the tempo type of the metronome",
+    "\tExample: `tt dotted-quarter` changes the current tempo type \
+    from whatever to dotted quarter note equals",
+    "subdivision, s <SUBDIVISION>: Set the subdivision of the metronome. \
+    Type `s` to clear subdivision",
+    "subdivision-setting, ss <SUBDIVISION_SETTING>: Set which subdivided beat \
+    to play. \"x\" represents play and \"-\" represents silent.",
+    "\tExample: `ss -x` with a subdivision of 2 will only play downbeat. \
+    Type `ss` to clear subdivision setting.",
+    "\tYou can set subdivision setting \
+    if there are no subdivision and the time signature is eights, to change its \
+    subdivision",
+    "accent, a <PATTERN>: Set a custom accent for every beat in the measure. \
+    \"X\" is accented, \"x\" is a normal beat and \".\" is played like a subdivision.",
+    "\tExample: `accent X.x.xx.` in 7/8. Type `accent` to go back to the default \
+    accents of the time signature.",
+    "ramp <START> <TARGET> <STEP> <BARS> [hold | restart] | stop: Start a speed \
+    trainer, which changes the tempo by STEP every BARS bars until it reaches TARGET, \
+    then holds that tempo or restarts from START. Use `ramp stop` to stop it.",
+    "\tExample: `ramp 80 140 4 2` starts at 80 and adds 4 every 2 bars until 140",
+    "volume, v [accented | beat | subdivision | poly] <PERCENT>: Set the master \
+    volume, or the volume of one kind of beat, from 0 to 100.",
+    "\tExample: `volume subdivision 40` lets subdivisions sit under the beats",
+    "poly <PULSES> [beat | bar] [pan] | off: Play a polyrhythm layer with its own \
+    sound, which divides every beat, or the whole bar, into PULSES. Add `pan` to put \
+    the main layer on the left and the polyrhythm on the right. Use `poly off` to stop it.",
+    "\tExample: `poly 3` with a subdivision of 2 plays 3 against 2",
+    "gap <PLAY BARS> <MUTE BARS> | off: Play the click for some bars, then mute it for \
+    some bars while the beats keep going, to check that you hold the tempo. Use \
+    `gap off` to stop it.",
+    "\tExample: `gap 4 2` plays 4 bars, then mutes 2",
+    "dropout <PERCENT> [keep-downbeats] [seed <SEED>] | off: Mute each click at random, \
+    with a chance of PERCENT. Add `keep-downbeats` to always play the first beat of the \
+    bar. Using the same seed again drops the same beats. Use `dropout off` to stop it.",
+    "\tExample: `dropout 30 keep-downbeats seed 7`",
+    "sound <accented | beat | subdivision | poly | alarm> <FILE> | synth <WAVEFORM> \
+    [PITCH] [DECAY] [LENGTH] | default: Play a WAV, OGG, FLAC or MP3 file, or a \
+    synthesized sine, square or noise click, instead of the built-in sound. Use \
+    `default` to go back to the built-in sound.",
+    "\tExample: `sound accented samples/woodblock.wav`, with the path relative to where \
+    the metronome was started",
+    "\tExample: `sound accented synth sine 2000 20 60` is a 2000 Hz sine that decays \
+    over 20ms and lasts 60ms",
+    "program <FILE> | stop: Play through a practice program, which is a TOML file \
+    listing sections of bars, the tempo and time signature map of a MIDI file, or \
+    the measures of a MusicXML score. Use `program stop` to stop it.",
+    "goto <SECTION>: Jump to the section with that name at the start of the next bar",
+    "loop <SECTION> | off: Keep repeating the section with that name. Use `loop off` \
+    to continue on to the next sections.",
+    "timer <TIME> | stop: Set a timer, with the format of `HH:MM:SS`, \
+    where hours and minutes are optional. Use `timer stop` to stop the timer.",
+    "tap [TAPS PER BEAT] | stop: Enters tap mode. Press any key for each beat, and after 4 \
+    taps, the tempo of the metronome will follow the tapped tempo, measured over \
+    the last 8 taps with stray taps ignored. Use `tap 2` to tap eighth notes in \
+    quarter note time, for example. Press `q` or use `tap stop` to stop",
+    "accuracy | stop: Tap along with the click, pressing any key on each beat or \
+    subdivision. Shows how many milliseconds early (-) or late (+) each tap is, and \
+    whether each bar was rushing or dragging. Press `q` to stop and see a summary",
+];
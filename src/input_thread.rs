@@ -1,57 +1,232 @@
 use std::{
-    io,
+    io::{self, Write},
     sync::{Arc, RwLock, mpsc},
     thread,
+    time::Instant,
 };
 
 use crossterm::{
     ExecutableCommand,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     terminal::{Clear, ClearType},
 };
+use key_mode::KeyMode;
+use keymap::{KeyAction, Keymap};
 
-use crate::{
-    TAPS_NEEDED, metronome::data::MetronomeData, tempo_measurer::TempoMeasurer,
-    user_input::UserInput,
-};
+use metronome::{MetronomeData, UserInput};
+
+use crate::ui::screen;
+
+pub mod key_mode;
+pub mod keymap;
+
+/// Records a tap, which changes the tempo once there are enough taps
+fn tap(sender: &mpsc::Sender<UserInput>) -> anyhow::Result<()> {
+    sender.send(UserInput::TapBeat(Instant::now()))?;
+
+    Ok(())
+}
+
+/// Whether key presses are taps, either in tap mode or when tapping along
+fn is_tapping(metronome_data: &RwLock<MetronomeData>) -> bool {
+    let metronome_data = metronome_data.read().unwrap();
+    metronome_data.tap_mode || metronome_data.tap_along.is_some()
+}
+
+/// Waits until the metronome has handled every command sent so far. They're handled
+/// in order, so the answer to a status request only comes after them
+fn wait_until_handled(sender: &mpsc::Sender<UserInput>) -> anyhow::Result<()> {
+    let (status_sender, status_receiver) = mpsc::channel();
+    sender.send(UserInput::SendStatus(status_sender))?;
+    status_receiver.recv()?;
+
+    Ok(())
+}
+
+/// Leaves tap mode or stops tapping along
+fn stop_tapping(
+    metronome_data: &RwLock<MetronomeData>,
+    sender: &mpsc::Sender<UserInput>,
+) -> anyhow::Result<()> {
+    if metronome_data.read().unwrap().tap_along.is_some() {
+        sender.send(UserInput::StopTapAlong)?;
+        return Ok(());
+    }
+
+    sender.send(UserInput::StopTap)?;
+
+    Ok(())
+}
+
+/// Taps on every key press until tapping is stopped with `q` or escape, so taps don't
+/// need a return after each one
+fn read_taps(
+    metronome_data: &RwLock<MetronomeData>,
+    sender: &mpsc::Sender<UserInput>,
+) -> anyhow::Result<()> {
+    let _key_mode = KeyMode::enable()?;
+
+    loop {
+        let key_event = read_key()?;
+
+        if !is_tapping(metronome_data) {
+            return Ok(());
+        }
+
+        match key_event.code {
+            _ if is_ctrl_c(&key_event) => sender.send(UserInput::Quit)?,
+            KeyCode::Esc | KeyCode::Char('q') => return stop_tapping(metronome_data, sender),
+            _ => tap(sender)?,
+        }
+    }
+}
 
 pub fn start_input_thread(
     metronome_data: Arc<RwLock<MetronomeData>>,
     sender: mpsc::Sender<UserInput>,
 ) {
     thread::spawn(move || -> anyhow::Result<()> {
-        let mut tempo_measurer = TempoMeasurer::new();
-
         loop {
             let mut input_str = String::new();
 
             io::stdin().read_line(&mut input_str)?;
-            io::stdout().execute(Clear(ClearType::All))?;
+            screen().execute(Clear(ClearType::All))?;
 
             let input_str = input_str.trim();
 
-            if metronome_data.read().unwrap().tap_mode {
+            // Only reached if keys can't be read one at a time, in which case each
+            // tap is a return instead
+            if is_tapping(&metronome_data) {
                 if matches!(input_str, "quit" | "q") {
-                    metronome_data.write().unwrap().tap_mode = false;
-                    tempo_measurer.clear();
-                    sender.send(UserInput::Resume)?;
+                    stop_tapping(&metronome_data, &sender)?;
                     continue;
                 }
 
-                tempo_measurer.tap();
-
-                if tempo_measurer.num_tapped() >= TAPS_NEEDED {
-                    sender.send(UserInput::SetTempoDirect(tempo_measurer.calculate_tempo()))?;
-                } else {
-                    sender.send(UserInput::Clear)?;
-                }
-
+                tap(&sender)?;
                 continue;
             }
 
             let user_input = input_str.parse::<UserInput>();
             if let Ok(user_input) = user_input {
+                let is_tap = matches!(user_input, UserInput::Tap(_) | UserInput::StartTapAlong);
                 sender.send(user_input)?;
+
+                // Tapping along is refused while paused, and the next key press
+                // shouldn't be taken as a tap then
+                if is_tap
+                    && wait_until_handled(&sender).is_ok()
+                    && is_tapping(&metronome_data)
+                    && read_taps(&metronome_data, &sender).is_err()
+                {
+                    writeln!(screen(), "Press enter for each beat instead.")?;
+                }
+            }
+        }
+    });
+}
+
+fn is_ctrl_c(key_event: &KeyEvent) -> bool {
+    key_event.code == KeyCode::Char('c') && key_event.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// Waits for the next key press, skipping releases and any other events
+fn read_key() -> io::Result<KeyEvent> {
+    loop {
+        if let Event::Key(key_event) = event::read()?
+            && key_event.kind == KeyEventKind::Press
+        {
+            return Ok(key_event);
+        }
+    }
+}
+
+/// Reads a command typed after `:`, echoing it as it's typed. Returns `None` if it's
+/// cancelled with escape or Ctrl+C
+fn read_command_line() -> io::Result<Option<String>> {
+    let mut command = String::new();
+    let mut screen = screen();
+
+    write!(screen, ":")?;
+    screen.flush()?;
+
+    loop {
+        let key_event = read_key()?;
+
+        match key_event.code {
+            _ if is_ctrl_c(&key_event) => {
+                write!(screen, "\r\n")?;
+                return Ok(None);
+            }
+            KeyCode::Esc => {
+                write!(screen, "\r\n")?;
+                return Ok(None);
+            }
+            KeyCode::Enter => {
+                write!(screen, "\r\n")?;
+                return Ok(Some(command));
+            }
+            KeyCode::Backspace if command.pop().is_some() => write!(screen, "\x08 \x08")?,
+            KeyCode::Char(c) => {
+                command.push(c);
+                write!(screen, "{}", c)?;
+            }
+            _ => {}
+        }
+
+        screen.flush()?;
+    }
+}
+
+/// Like `start_input_thread`, but every key does something as soon as it's pressed,
+/// following `keymap`. Typing `:` opens the usual command line
+pub fn start_key_input_thread(
+    metronome_data: Arc<RwLock<MetronomeData>>,
+    sender: mpsc::Sender<UserInput>,
+    keymap: Keymap,
+) -> io::Result<()> {
+    let key_mode = KeyMode::enable()?;
+
+    thread::spawn(move || -> anyhow::Result<()> {
+        let _key_mode = key_mode;
+
+        loop {
+            let key_event = read_key()?;
+            let action = keymap.action(&key_event);
+
+            if is_ctrl_c(&key_event) {
+                sender.send(UserInput::Quit)?;
+                continue;
+            }
+
+            if is_tapping(&metronome_data) {
+                match key_event.code {
+                    KeyCode::Esc | KeyCode::Char('q') => stop_tapping(&metronome_data, &sender)?,
+                    _ => tap(&sender)?,
+                }
+                continue;
+            }
+
+            match action {
+                Some(KeyAction::Tap) => {
+                    sender.send(UserInput::Tap(String::new()))?;
+                    tap(&sender)?;
+                }
+                Some(KeyAction::CommandLine) => {
+                    let command = read_command_line()?;
+                    screen().execute(Clear(ClearType::All))?;
+
+                    if let Some(Ok(user_input)) = command.map(|command| command.parse()) {
+                        sender.send(user_input)?;
+                    }
+                }
+                Some(KeyAction::Command(user_input)) => {
+                    screen().execute(Clear(ClearType::All))?;
+                    sender.send(user_input.clone())?;
+                }
+                None => {}
             }
         }
     });
+
+    Ok(())
 }
@@ -0,0 +1,31 @@
+use crossterm::terminal;
+use std::io;
+
+/// Reads keys as soon as they're pressed, without echoing them, until it's dropped.
+/// Ctrl+C is read as a key instead of stopping the metronome. A new line doesn't go
+/// back to the start of the line in this mode, so lines printed meanwhile end with
+/// `\r\n`
+pub struct KeyMode(());
+
+impl KeyMode {
+    pub fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(Self(()))
+    }
+}
+
+impl Drop for KeyMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Leaves key mode once it's dropped, whichever thread turned it on, so the terminal
+/// is restored however the metronome stops
+pub struct RestoreTerminal;
+
+impl Drop for RestoreTerminal {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{Context, anyhow};
+use crossterm::event::{KeyCode, KeyEvent};
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path, str::FromStr};
+
+use metronome::UserInput;
+
+/// The keys used when there's no keymap file, which a keymap file adds to or overrides
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("space", "toggle"),
+    ("+", "tempo +1"),
+    ("=", "tempo +1"),
+    ("-", "tempo -1"),
+    ("right", "tempo +1"),
+    ("left", "tempo -1"),
+    ("up", "tempo +5"),
+    ("down", "tempo -5"),
+    ("1", "subdivision 1"),
+    ("2", "subdivision 2"),
+    ("3", "subdivision 3"),
+    ("4", "subdivision 4"),
+    ("5", "subdivision 5"),
+    ("6", "subdivision 6"),
+    ("7", "subdivision 7"),
+    ("8", "subdivision 8"),
+    ("t", "tap"),
+    ("a", "accuracy"),
+    (":", "command"),
+    ("h", "help"),
+    ("q", "quit"),
+];
+
+/// What pressing a key does
+#[derive(Debug, Clone)]
+pub enum KeyAction {
+    /// Taps the tempo, starting tap mode if it isn't on yet
+    Tap,
+    /// Opens the command line, where any command can be typed
+    CommandLine,
+    /// Runs a command, written the same way as on the command line
+    Command(UserInput),
+}
+
+impl FromStr for KeyAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tap" => Ok(Self::Tap),
+            "command" => Ok(Self::CommandLine),
+            _ => match s.parse::<UserInput>()? {
+                UserInput::Unknown(command) => Err(anyhow!("Unknown command `{}`!", command)),
+                user_input => Ok(Self::Command(user_input)),
+            },
+        }
+    }
+}
+
+fn parse_key(key_name: &str) -> anyhow::Result<KeyCode> {
+    let key_code = match key_name.to_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "esc" => KeyCode::Esc,
+        _ => {
+            let mut chars = key_name.chars();
+
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => KeyCode::Char(c),
+                _ => return Err(anyhow!("Unknown key `{}`!", key_name)),
+            }
+        }
+    };
+
+    Ok(key_code)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeymapFile {
+    keys: HashMap<String, String>,
+}
+
+/// Which key does what when the metronome is controlled with single keys
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<KeyCode, KeyAction>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = DEFAULT_BINDINGS
+            .iter()
+            .map(|(key_name, action)| {
+                (
+                    parse_key(key_name).unwrap(),
+                    action.parse::<KeyAction>().unwrap(),
+                )
+            })
+            .collect();
+
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let keymap_str = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read keymap `{}`", path.display()))?;
+
+        keymap_str
+            .parse()
+            .with_context(|| format!("Invalid keymap `{}`", path.display()))
+    }
+
+    pub fn action(&self, key_event: &KeyEvent) -> Option<&KeyAction> {
+        self.bindings.get(&key_event.code)
+    }
+}
+
+impl FromStr for Keymap {
+    type Err = anyhow::Error;
+
+    /// Parses a TOML keymap, with a `[keys]` table binding each key to an action. An
+    /// empty action unbinds the key
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keymap_file: KeymapFile = toml::from_str(s)?;
+        let mut keymap = Self::default();
+
+        for (key_name, action) in keymap_file.keys {
+            let key_code = parse_key(&key_name)?;
+
+            if action.is_empty() {
+                keymap.bindings.remove(&key_code);
+                continue;
+            }
+
+            let action = action
+                .parse::<KeyAction>()
+                .with_context(|| format!("Invalid action for key `{}`", key_name))?;
+            keymap.bindings.insert(key_code, action);
+        }
+
+        Ok(keymap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input_thread::keymap::{KeyAction, Keymap};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use metronome::UserInput;
+    use std::str::FromStr;
+
+    fn action(keymap: &Keymap, key_code: KeyCode) -> Option<&KeyAction> {
+        keymap.action(&KeyEvent::new(key_code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn keymap_overrides_defaults() {
+        let keymap = Keymap::from_str(
+            r#"
+            [keys]
+            up = "tempo +10"
+            m = "volume 0"
+            q = ""
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            action(&keymap, KeyCode::Up),
+            Some(KeyAction::Command(UserInput::NudgeTempo(10)))
+        ));
+        assert!(matches!(
+            action(&keymap, KeyCode::Char('m')),
+            Some(KeyAction::Command(UserInput::SetVolume(_)))
+        ));
+        assert!(matches!(
+            action(&keymap, KeyCode::Char(' ')),
+            Some(KeyAction::Command(UserInput::TogglePause))
+        ));
+        assert!(matches!(
+            action(&keymap, KeyCode::Char('t')),
+            Some(KeyAction::Tap)
+        ));
+        assert!(action(&keymap, KeyCode::Char('q')).is_none());
+    }
+
+    #[test]
+    fn invalid_keymap() {
+        assert!(Keymap::from_str("[keys]\nup = \"jump\"").is_err());
+        assert!(Keymap::from_str("[keys]\npageup = \"tap\"").is_err());
+        assert!(Keymap::from_str("[bindings]\nup = \"tap\"").is_err());
+    }
+}
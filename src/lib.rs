@@ -0,0 +1,58 @@
+//! A console-based metronome, along with the beat engine behind it.
+//!
+//! [`Metronome`] is the engine. It's built from a [`MetronomeData`] with the starting
+//! settings, takes commands as [`UserInput`] through a channel, and tells listeners
+//! about every beat with [`MetronomeEvent`]. The terminal app is one of its clients,
+//! and the OSC server, web page, daemon and event log are others.
+//!
+//! ```no_run
+//! use metronome::{Metronome, MetronomeData, MetronomeEvent, TempoType, UserInput};
+//! use std::{thread, time::Duration};
+//!
+//! let time_signature = "7/8".parse()?;
+//! let metronome_data =
+//!     MetronomeData::with_settings(120, time_signature, TempoType::EighthNote, 1)?;
+//! let mut metronome = Metronome::silent(metronome_data);
+//!
+//! metronome.on_event(|event| {
+//!     if let MetronomeEvent::Beat { beat_info, click: Some(accent), .. } = event {
+//!         println!("{:?} on beat {}", accent, beat_info.current_beat);
+//!     }
+//!     Ok(())
+//! });
+//!
+//! // Commands are written the same way as in the terminal
+//! let sender = metronome.sender();
+//! thread::spawn(move || {
+//!     thread::sleep(Duration::from_secs(4));
+//!     sender.send("tempo 132".parse::<UserInput>().unwrap()).unwrap();
+//!     thread::sleep(Duration::from_secs(4));
+//!     // The engine stops once every sender is dropped
+//! });
+//!
+//! metronome.run()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod cli;
+pub mod clock;
+pub mod events;
+pub mod metronome;
+pub mod tempo_measurer;
+pub mod timer;
+pub mod user_input;
+
+pub use clock::{Clock, ManualClock, SharedClock, SystemClock};
+pub use metronome::{
+    data::{
+        MetronomeData, TempoType, TimeSignature,
+        beat::{BeatInfo, MetronomeBeatTracker, accent::MetronomeBeatAccent},
+        subdivision_setting::SubdivisionSetting,
+    },
+    engine::{Metronome, MetronomeEvent},
+};
+pub use timer::Timer;
+pub use user_input::UserInput;
+
+/// Number of taps needed before tap mode changes the tempo
+pub const TAPS_NEEDED: usize = 4;
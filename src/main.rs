@@ -1,135 +1,226 @@
-use crate::timer::render_tracker::TimerRenderTracker;
+use anyhow::Context;
 use clap::Parser;
 use crossterm::{
     ExecutableCommand,
     terminal::{Clear, ClearType},
 };
-use input_thread::start_input_thread;
 use metronome::{
-    data::{
-        MetronomeData, SUBDIVISION_RANGE, TEMPO_RANGE, TempoType, TimeSignature,
-        beat::{MetronomeBeatTracker, accent::get_metronome_beat_accent},
-    },
-    sound::play_metronome_sound,
+    Metronome, MetronomeData, MetronomeEvent, UserInput,
+    cli::{Cli, Command},
+    events::EventLog,
+    metronome::{midi::write_midi_file, render::render_click_track, sound::SoundSlot},
 };
-use std::{
-    io,
-    sync::{Arc, RwLock, mpsc},
-    thread,
-    time::Duration,
+use std::{io::Write, sync::Arc, time::Instant};
+
+use help_menu::HELP_LINES;
+use input_thread::{
+    key_mode::RestoreTerminal, keymap::Keymap, start_input_thread, start_key_input_thread,
 };
-use timer::play_timer_alarm;
-use ui::Ui;
-use user_input::UserInput;
+use osc::OscServer;
+use ui::{Ui, screen};
+use web::WebServer;
 
+mod daemon;
+mod help_menu;
 mod input_thread;
-mod metronome;
-mod tempo_measurer;
-mod timer;
+mod osc;
 mod ui;
-mod user_input;
-
-const TICK_LENGTH: Duration = Duration::from_micros(1);
-
-/// Number of taps needed before tap mode changes the tempo
-const TAPS_NEEDED: usize = 4;
-
-/// A metronome written in Rust. Once entered, you can type in commands to change the
-/// various settings within the metronome, such as the tempo, the time signature, the
-/// subdivision, etc. Once entered the metronome, type in `help` and press enter to see
-/// more detailed information about all the commands
-#[derive(Parser, Clone, Debug)]
-#[command(version, about, long_about)]
-struct Cli {
-    /// The tempo for the metronome, in beats per minute. Cannot be less than 10,
-    /// or greater than 400
-    #[arg(default_value_t = 60, value_parser = clap::value_parser!(i32).range(TEMPO_RANGE))]
-    tempo: i32,
-
-    /// The time signature for the metronome, in the format of a fraction. For example,
-    /// `4/4` or `6/8`
-    #[arg(default_value_t = TimeSignature::default())]
-    time_signature: TimeSignature,
-
-    /// The tempo type for the metronome. By default, it's quarter note equals,
-    /// but for time signatures like `6/8`, it'll be dotted quarter equals, and
-    /// for time signatures like `2/2`, it'll be half-note equals.
-    #[arg(value_enum, short, long)]
-    tempo_type: Option<TempoType>,
-
-    /// The subdivision for the metronome, in terms of numbers. For example,
-    /// `2` represents splitting a beat into 2
-    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(i32).range(SUBDIVISION_RANGE))]
-    subdivision: i32,
-}
+mod web;
 
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
-    let metronome_data = Arc::new(RwLock::new(MetronomeData::new(&cli)));
-
-    let mut ui = Ui::new(Arc::clone(&metronome_data));
-    let mut metronome_beat_tracker = MetronomeBeatTracker::new(Arc::clone(&metronome_data));
-    let mut timer_render_tracker = TimerRenderTracker::new(Arc::clone(&metronome_data));
-
-    let mut _metronome_sound_data;
-    let mut _timer_alarm_sound_data: Option<(rodio::OutputStream, rodio::Sink)> = None;
-
-    let (sender, receiver) = mpsc::channel::<UserInput>();
-
-    // The thread for input
-    start_input_thread(Arc::clone(&metronome_data), sender);
+    match &cli.command {
+        Some(Command::Render(render_args)) => {
+            render_click_track(render_args)?;
+            writeln!(
+                screen(),
+                "Click track written to {}",
+                render_args.output.display()
+            )?;
+            return Ok(());
+        }
+        Some(Command::Midi(midi_args)) => {
+            write_midi_file(midi_args)?;
+            writeln!(
+                screen(),
+                "Click map written to {}",
+                midi_args.output.display()
+            )?;
+            return Ok(());
+        }
+        Some(Command::Ctl(ctl_args)) => return daemon::send_command(ctl_args),
+        None => {}
+    }
 
-    io::stdout().execute(Clear(ClearType::All))?;
+    // Whichever thread reads keys, the terminal is restored on every way out
+    let _restore_terminal = RestoreTerminal;
 
-    loop {
-        thread::sleep(TICK_LENGTH);
+    let mut metronome_data = MetronomeData::new(&cli.metronome_args)?;
+    if let Some(ref program_path) = cli.program {
+        metronome_data.load_program_file(program_path)?;
+    }
 
-        if !metronome_data.read().unwrap().is_paused && metronome_beat_tracker.should_play_beat() {
-            metronome_beat_tracker.move_to_next_subdivided_beat();
+    let mut metronome = Metronome::new(metronome_data)?;
+    let metronome_data = metronome.data();
+    let sender = metronome.sender();
+
+    let mut ui = match cli.daemon {
+        true => Ui::headless(Arc::clone(&metronome_data)),
+        false => Ui::new(Arc::clone(&metronome_data)),
+    };
+
+    let osc_server = match cli.osc {
+        Some(port) => Some(
+            OscServer::start(port, cli.osc_send.clone(), sender.clone())
+                .with_context(|| format!("Failed to listen for OSC on port {}", port))?,
+        ),
+        None => None,
+    };
+
+    let web_server = match cli.web {
+        Some(port) => Some(
+            WebServer::start(
+                cli.web_address,
+                port,
+                cli.web_host.clone(),
+                Arc::clone(&metronome_data),
+                sender.clone(),
+            )
+            .with_context(|| format!("Failed to serve the control page on port {}", port))?,
+        ),
+        None => None,
+    };
+
+    // Events written to stdout move the screen to stderr, before anything is printed
+    if cli.events.is_some() && cli.events_file.is_none() {
+        ui::move_screen_to_stderr();
+    }
+    let mut event_log = match cli.events {
+        Some(_) => Some(
+            EventLog::open(cli.events_file.as_deref(), Instant::now())
+                .context("Failed to open the event log")?,
+        ),
+        None => None,
+    };
+
+    // The thread for input. The daemon's socket is kept until the metronome stops
+    let _control_server = if cli.daemon {
+        let socket_path = cli
+            .socket
+            .clone()
+            .unwrap_or_else(daemon::default_socket_path);
+
+        let control_server = daemon::start_control_server(&socket_path, sender)
+            .with_context(|| format!("Failed to listen on {}", socket_path.display()))?;
+        writeln!(
+            screen(),
+            "Listening for `metronome ctl` on {}",
+            socket_path.display()
+        )?;
+        Some(control_server)
+    } else if cli.keys || cli.keymap.is_some() {
+        let keymap = match cli.keymap {
+            Some(ref keymap_path) => Keymap::load(keymap_path)?,
+            None => Keymap::default(),
+        };
+
+        start_key_input_thread(Arc::clone(&metronome_data), sender, keymap)?;
+        None
+    } else {
+        start_input_thread(Arc::clone(&metronome_data), sender);
+        None
+    };
+
+    if !cli.daemon {
+        screen().execute(Clear(ClearType::All))?;
+    }
 
-            let d = metronome_data.read().unwrap();
-            let should_play_subdivision_beat = d.subdivision_setting.should_play_subdivision_beat(
-                d.beat_info,
-                d.time_signature_is_eighths(),
-                d.subdivision() > 1,
-            );
+    // Sounds are loaded after clearing the screen, so any errors stay visible
+    let sound_sources = cli
+        .sound_args
+        .sound_sources()
+        .into_iter()
+        .map(|(accent, sound_source)| (SoundSlot::Click(accent), sound_source))
+        .chain(
+            cli.alarm_sound
+                .clone()
+                .map(|sound_source| (SoundSlot::Alarm, sound_source)),
+        );
+    for (sound_slot, sound_source) in sound_sources {
+        if let Err(err) = metronome.set_sound(sound_slot, Some(&sound_source)) {
+            writeln!(screen(), "{:#}", err)?;
+        }
+    }
 
-            if should_play_subdivision_beat {
-                _metronome_sound_data =
-                    play_metronome_sound(get_metronome_beat_accent(d.beat_accents(), d.beat_info))?;
+    metronome.on_event(move |event| {
+        match event {
+            MetronomeEvent::Beat {
+                timestamp,
+                beat_info,
+                ..
+            } => {
+                if let Some(ref osc_server) = osc_server {
+                    osc_server.send_beat(*beat_info);
+                }
+                if let Some(ref web_server) = web_server {
+                    web_server.send_beat(*beat_info);
+                }
+                if let Some(ref mut event_log) = event_log {
+                    let d = metronome_data.read().unwrap();
+                    // Programs and ramps change the settings as the bar starts
+                    event_log.log_state(&d)?;
+                    event_log.log_click(*timestamp, &d)?;
+                }
+
+                if beat_info.subdivided_beat != 0 {
+                    return Ok(());
+                }
             }
+            MetronomeEvent::PolyrhythmPulse { timestamp, .. } => {
+                if let Some(ref mut event_log) = event_log {
+                    event_log.log_polyrhythm_pulse(*timestamp, &metronome_data.read().unwrap())?;
+                }
 
-            drop(d);
-
-            if metronome_beat_tracker.is_downbeat() {
-                ui.render()?;
+                return Ok(());
+            }
+            MetronomeEvent::TimerTick => {}
+            MetronomeEvent::Command(user_input) => {
+                match user_input {
+                    // `metronome ctl` might have hung up already
+                    UserInput::SendStatus(status_sender) => {
+                        let _ = status_sender.send(ui.status_text());
+                    }
+                    UserInput::Help => ui.print_lines(&HELP_LINES.join("\n"))?,
+                    _ => {}
+                }
+                if !matches!(user_input, UserInput::SendStatus(_)) {
+                    ui.print_mode_text()?;
+                }
+                if let Some(ref mut event_log) = event_log {
+                    event_log.log_state(&metronome_data.read().unwrap())?;
+                }
+            }
+            MetronomeEvent::CommandFailed { error, .. } => {
+                ui.print_lines(error)?;
+                ui.print_mode_text()?;
+            }
+            MetronomeEvent::Message(message) => {
+                ui.print_lines(message)?;
+                return Ok(());
             }
         }
 
-        if timer_render_tracker.should_render_timer() {
-            ui.render()?;
-            timer_render_tracker.just_rendered();
-
-            let d = metronome_data.read().unwrap();
-            let timer = d.timer.as_ref().unwrap();
-
-            if timer.time_remaining().is_zero() {
-                _timer_alarm_sound_data = Some(play_timer_alarm()?);
-            }
+        ui.render()?;
+        if let Some(ref web_server) = web_server {
+            web_server.send_status();
         }
 
-        // If got a message from the input thread
-        if let Ok(message) = receiver.try_recv() {
-            metronome_data.write().unwrap().execute(&message);
+        Ok(())
+    });
 
-            match message {
-                UserInput::Pause => metronome_beat_tracker.offset_beat_timestamp(),
-                UserInput::StopTimer | UserInput::SetTimer(_) => _timer_alarm_sound_data = None,
-                _ => {}
-            }
+    metronome.run()?;
+    write!(screen(), "Goodbye!\r\n")?;
 
-            ui.render()?;
-        }
-    }
+    Ok(())
 }
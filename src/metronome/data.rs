@@ -1,21 +1,42 @@
-use crate::timer::Timer;
-use crate::{Cli, metronome::help_menu::print_help, user_input::UserInput};
+use crate::clock::{SharedClock, SystemClock};
+use crate::timer::{Timer, parse_duration};
+use crate::{TAPS_NEEDED, tempo_measurer::TempoMeasurer};
+use crate::{cli::MetronomeArgs, user_input::UserInput};
 use TempoType::*;
 use anyhow::anyhow;
 use beat::BeatInfo;
-use beat::accent::{MetronomeBeatAccent, get_beat_accents_from_time_signature};
+use beat::accent::{
+    AccentPattern, MetronomeBeatAccent, get_beat_accents_from_time_signature,
+    get_metronome_beat_accent,
+};
+use beat_grouping::{BeatGrouping, MAX_GROUPED_BEATS};
 use clap::ValueEnum;
+use dropout::Dropout;
+use gap_click::GapClick;
+use polyrhythm::{POLYRHYTHM_PAN, PolyrhythmLayer, PolyrhythmScope};
+use program::{NextBar, Program, ProgramPlayer, Section};
+use std::path::Path;
 use std::{
     fmt::{self, Display, Formatter},
     ops::RangeInclusive,
-    process,
     str::FromStr,
     time::Duration,
 };
 use subdivision_setting::SubdivisionSetting;
+use tap_along::TapAlong;
+use tempo_ramp::TempoRamp;
+use volume::Volume;
 
 pub mod beat;
+pub mod beat_grouping;
+pub mod dropout;
+pub mod gap_click;
+pub mod polyrhythm;
+pub mod program;
 pub mod subdivision_setting;
+pub mod tap_along;
+pub mod tempo_ramp;
+pub mod volume;
 
 pub const TEMPO_MIN: i32 = 10;
 pub const TEMPO_MAX: i32 = 400;
@@ -50,7 +71,7 @@ pub enum TempoType {
 }
 
 impl TempoType {
-    fn to_note_length(self) -> f64 {
+    pub fn to_note_length(self) -> f64 {
         match self {
             QuarterNote => 1.0,
             EighthNote => 0.5,
@@ -115,13 +136,37 @@ impl FromStr for TempoType {
 }
 
 /// The time signature, with the first i32 representing the number of beats in a
-/// measure, and the second i32 representing what each beat is equivalent to
-#[derive(Debug, Clone, Copy)]
-pub struct TimeSignature(pub i32, pub i32);
+/// measure, and the second i32 representing what each beat is equivalent to. Additive
+/// time signatures like `2+2+3/8` also carry how their beats are grouped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignature(pub i32, pub i32, pub Option<BeatGrouping>);
 
 impl Default for TimeSignature {
     fn default() -> Self {
-        Self(4, 4)
+        Self(4, 4, None)
+    }
+}
+
+impl TimeSignature {
+    /// How the beats are grouped. Time signatures that end in eighths are grouped by
+    /// default, others only if the grouping was written out, like `3+2/4`
+    pub fn beat_grouping(&self) -> Option<BeatGrouping> {
+        match self.2 {
+            Some(beat_grouping) => Some(beat_grouping),
+            None if self.1 == 8 && self.0 <= MAX_GROUPED_BEATS => {
+                Some(BeatGrouping::default_for_eighths(self.0))
+            }
+            None => None,
+        }
+    }
+
+    /// The time signature as it's typed in commands, like `2+2+3/8`, so it parses back
+    /// into the same one
+    pub fn notation(&self) -> String {
+        match self.2 {
+            Some(beat_grouping) => format!("{}/{}", beat_grouping, self.1),
+            None => format!("{}/{}", self.0, self.1),
+        }
     }
 }
 
@@ -137,7 +182,15 @@ impl FromStr for TimeSignature {
             ));
         }
 
-        let numerator: i32 = numbers[0].parse()?;
+        let beat_grouping = if numbers[0].contains('+') {
+            Some(numbers[0].parse::<BeatGrouping>()?)
+        } else {
+            None
+        };
+        let numerator: i32 = match beat_grouping {
+            Some(beat_grouping) => beat_grouping.num_beats(),
+            None => numbers[0].parse()?,
+        };
         let denominator: i32 = numbers[1].parse()?;
 
         if numerator < 1 {
@@ -152,13 +205,19 @@ impl FromStr for TimeSignature {
             ));
         }
 
-        Ok(Self(numerator, denominator))
+        Ok(Self(numerator, denominator, beat_grouping))
     }
 }
 
 impl Display for TimeSignature {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}/{}", self.0, self.1)
+        write!(f, "{}/{}", self.0, self.1)?;
+
+        if let Some(beat_grouping) = self.2 {
+            write!(f, " ({})", beat_grouping)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -173,7 +232,16 @@ pub struct MetronomeData {
     duration_per_subdivided_beat: Duration,
     pub is_paused: bool,
     pub tap_mode: bool,
+    pub tempo_measurer: TempoMeasurer,
+    pub tap_along: Option<TapAlong>,
     pub timer: Option<Timer>,
+    pub tempo_ramp: Option<TempoRamp>,
+    pub program: Option<ProgramPlayer>,
+    pub polyrhythm: Option<PolyrhythmLayer>,
+    pub gap_click: Option<GapClick>,
+    pub dropout: Option<Dropout>,
+    pub volume: Volume,
+    clock: SharedClock,
 }
 
 // Getters and setters
@@ -204,6 +272,27 @@ impl MetronomeData {
     pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
         self.time_signature = time_signature;
         self.set_tempo_type(TempoType::get_default_based(time_signature));
+        self.reset_beat_accents();
+    }
+
+    /// Replaces the default accents of the time signature with a custom pattern,
+    /// which must have an accent for every beat in the measure
+    pub fn set_beat_accents(&mut self, accent_pattern: AccentPattern) -> anyhow::Result<()> {
+        if accent_pattern.0.len() != self.time_signature.0 as usize {
+            return Err(anyhow!(
+                "Accent pattern `{}` has {} beats, but the time signature {} has {}!",
+                accent_pattern,
+                accent_pattern.0.len(),
+                self.time_signature,
+                self.time_signature.0
+            ));
+        }
+
+        self.beat_accents = accent_pattern.0;
+        Ok(())
+    }
+
+    pub fn reset_beat_accents(&mut self) {
         self.beat_accents = get_beat_accents_from_time_signature(self.time_signature);
     }
 
@@ -232,37 +321,260 @@ impl MetronomeData {
     pub fn beat_accents(&self) -> &[MetronomeBeatAccent] {
         self.beat_accents.as_slice()
     }
+
+    /// The clock everything that keeps time for this metronome goes by
+    pub fn clock(&self) -> SharedClock {
+        SharedClock::clone(&self.clock)
+    }
+
+    /// Changes the clock, which has to happen before the metronome starts
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+    }
 }
 
 impl MetronomeData {
-    pub fn new(cli: &Cli) -> Self {
-        let tempo_type = cli
-            .tempo_type
-            .unwrap_or(TempoType::get_default_based(cli.time_signature));
+    /// Starts at `tempo` in `time_signature`, counting the tempo in `tempo_type` and
+    /// splitting each beat into `subdivision`. Everything else starts out off, and can
+    /// be changed later with commands
+    pub fn with_settings(
+        tempo: i32,
+        time_signature: TimeSignature,
+        tempo_type: TempoType,
+        subdivision: i32,
+    ) -> anyhow::Result<Self> {
+        if !is_tempo_valid(tempo) {
+            return Err(anyhow!(
+                "Invalid tempo `{}`! Must be a valid whole number between {}-{}!",
+                tempo,
+                TEMPO_MIN,
+                TEMPO_MAX
+            ));
+        }
+        if !is_subdivision_valid(subdivision) {
+            return Err(anyhow!("Invalid subdivision {}!", subdivision));
+        }
 
         let mut new_value = Self {
-            tempo: cli.tempo,
-            time_signature: cli.time_signature,
-            beat_accents: get_beat_accents_from_time_signature(cli.time_signature),
+            tempo,
+            time_signature,
+            beat_accents: get_beat_accents_from_time_signature(time_signature),
             tempo_type,
-            subdivision: cli.subdivision,
+            subdivision: subdivision.max(1),
             subdivision_setting: SubdivisionSetting::default(),
-            beat_info: (cli.time_signature.0 - 1, cli.subdivision - 1).into(),
+            beat_info: BeatInfo::default(),
             duration_per_subdivided_beat: Duration::ZERO,
             is_paused: false,
             tap_mode: false,
+            tempo_measurer: TempoMeasurer::new(),
+            tap_along: None,
             timer: None,
+            tempo_ramp: None,
+            program: None,
+            polyrhythm: None,
+            gap_click: None,
+            dropout: None,
+            volume: Volume::default(),
+            clock: SystemClock::shared(),
         };
 
         new_value.recalculate_duration_per_subdivided_beat();
-        new_value
+        new_value.move_before_downbeat();
+
+        Ok(new_value)
+    }
+
+    /// Starts with the settings given on the command line
+    pub fn new(args: &MetronomeArgs) -> anyhow::Result<Self> {
+        let tempo_type = args
+            .tempo_type
+            .unwrap_or(TempoType::get_default_based(args.time_signature));
+
+        let mut new_value = Self::with_settings(
+            args.tempo,
+            args.time_signature,
+            tempo_type,
+            args.subdivision,
+        )?;
+        new_value.subdivision_setting = args.subdivision_setting.clone().unwrap_or_default();
+        new_value.polyrhythm = args.poly;
+        new_value.gap_click = args.gap;
+        new_value.dropout = args.dropout;
+        new_value.volume = Volume {
+            master: args.volume,
+            accented: args.accented_volume,
+            beat: args.beat_volume,
+            subdivision: args.subdivision_volume,
+            polyrhythm: args.poly_volume,
+        };
+
+        if let Some(accent_pattern) = args.accents.clone() {
+            new_value.set_beat_accents(accent_pattern)?;
+        }
+
+        Ok(new_value)
+    }
+
+    pub fn duration_per_subdivided_beat(&self) -> Duration {
+        self.duration_per_subdivided_beat
+    }
+
+    pub fn move_to_next_subdivided_beat(&mut self) {
+        self.beat_info
+            .next_subdivided_beat(self.time_signature.0, self.subdivision);
+
+        if self.beat_info == (0, 0) {
+            self.start_new_bar();
+        }
+
+        let has_click = self.pattern_click().is_some();
+        let is_downbeat = self.beat_info == (0, 0);
+        if let Some(ref mut dropout) = self.dropout {
+            dropout.next_beat(has_click, is_downbeat);
+        }
+    }
+
+    /// Moves to the last subdivided beat of the measure, so the next beat played is
+    /// the downbeat
+    fn move_before_downbeat(&mut self) {
+        self.beat_info = (self.time_signature.0 - 1, self.subdivision - 1).into();
+    }
+
+    /// Called on the downbeat of every bar, before it's played
+    fn start_new_bar(&mut self) {
+        if let Some(ref mut program) = self.program {
+            match program.next_bar() {
+                NextBar::SameSection => {}
+                NextBar::NewSection(section) => {
+                    let section = section.clone();
+                    self.apply_section(&section);
+                }
+                NextBar::Finished => {
+                    self.program = None;
+                    self.is_paused = true;
+                }
+            }
+        }
+
+        if let Some(ref mut gap_click) = self.gap_click {
+            gap_click.next_bar();
+        }
+
+        if let Some(ref mut tap_along) = self.tap_along {
+            tap_along.next_bar();
+        }
+
+        if let Some(ref mut tempo_ramp) = self.tempo_ramp {
+            let tempo = tempo_ramp.next_bar();
+
+            if tempo != self.tempo {
+                self.set_tempo(tempo);
+            }
+        }
     }
 
-    pub fn time_signature_is_eighths(&self) -> bool {
-        self.time_signature.1 == 8
+    /// Stops tapping along, returning how it went
+    fn stop_tap_along(&mut self) -> Option<String> {
+        self.tap_along.take().map(|tap_along| tap_along.summary())
     }
 
-    pub fn execute(&mut self, user_input: &UserInput) {
+    /// Switches to the settings of a program section
+    fn apply_section(&mut self, section: &Section) {
+        self.set_time_signature(section.time_signature);
+        if let Some(tempo_type) = section.tempo_type {
+            self.tempo_type = tempo_type;
+        }
+        self.set_tempo(section.tempo);
+        self.set_subdivision(section.subdivision);
+        self.subdivision_setting = section.subdivision_setting.clone();
+    }
+
+    /// Starts playing a program from its first section, on the next beat
+    pub fn load_program(&mut self, program: Program) {
+        let program = ProgramPlayer::new(program);
+
+        self.apply_section(program.current_section());
+        self.move_before_downbeat();
+        self.tempo_ramp = None;
+        self.program = Some(program);
+    }
+
+    pub fn load_program_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.load_program(Program::load(path)?);
+        Ok(())
+    }
+
+    /// If the current subdivided beat starts a span of the polyrhythm layer, returns
+    /// the layer along with how long the span lasts
+    pub fn polyrhythm_span(&self) -> Option<(PolyrhythmLayer, Duration)> {
+        let polyrhythm = self.polyrhythm?;
+
+        let starts_span = match polyrhythm.scope {
+            PolyrhythmScope::Beat => self.beat_info.subdivided_beat == 0,
+            PolyrhythmScope::Bar => self.beat_info == (0, 0),
+        };
+        let main_pulses = polyrhythm.main_pulses(self.time_signature.0, self.subdivision);
+
+        starts_span.then(|| {
+            (
+                polyrhythm,
+                self.duration_per_subdivided_beat * main_pulses as u32,
+            )
+        })
+    }
+
+    /// Where a click is heard, from -1 (left) to 1 (right). With a panned polyrhythm,
+    /// the main layer goes to the left and the polyrhythm to the right
+    pub fn click_pan(&self, accent: MetronomeBeatAccent) -> f32 {
+        match self.polyrhythm {
+            Some(polyrhythm) if polyrhythm.pan => match accent {
+                MetronomeBeatAccent::Polyrhythm => POLYRHYTHM_PAN,
+                _ => -POLYRHYTHM_PAN,
+            },
+            _ => 0.0,
+        }
+    }
+
+    /// Whether the gap click is silencing the current bar. Unlike pausing, the beats
+    /// keep going
+    pub fn is_muted(&self) -> bool {
+        self.gap_click.is_some_and(|gap_click| gap_click.is_muted())
+    }
+
+    /// Whether the subdivision setting plays the current subdivided beat
+    pub fn is_played_by_subdivision_setting(&self) -> bool {
+        self.subdivision_setting.should_play_subdivision_beat(
+            self.beat_info,
+            self.time_signature.beat_grouping(),
+            self.subdivision > 1,
+        )
+    }
+
+    /// Whether the dropout dropped the click of the current subdivided beat
+    pub fn is_dropped(&self) -> bool {
+        self.dropout.is_some_and(|dropout| dropout.is_dropped())
+    }
+
+    /// The accent of the click for the current subdivided beat, or `None` if the
+    /// subdivision setting or the gap click silences it
+    fn pattern_click(&self) -> Option<MetronomeBeatAccent> {
+        if self.is_paused || self.is_muted() {
+            return None;
+        }
+
+        self.is_played_by_subdivision_setting()
+            .then(|| get_metronome_beat_accent(self.beat_accents(), self.beat_info))
+    }
+
+    /// The accent of the click for the current subdivided beat, or `None` if it's
+    /// silenced or randomly dropped
+    pub fn current_click(&self) -> Option<MetronomeBeatAccent> {
+        self.pattern_click().filter(|_| !self.is_dropped())
+    }
+
+    /// Carries out a command. Returns a note for the user if there is one, like how
+    /// well they tapped along, or an error if the command couldn't be carried out
+    pub fn execute(&mut self, user_input: &UserInput) -> anyhow::Result<Option<String>> {
         use UserInput::*;
 
         match user_input {
@@ -271,68 +583,221 @@ impl MetronomeData {
                 self.is_paused = false;
                 self.beat_info.reset();
             }
-            Help => print_help(),
-            Clear => {}
+            TogglePause if self.is_paused => {
+                self.tap_mode = false;
+                self.is_paused = false;
+                self.beat_info.reset();
+            }
+            TogglePause => self.is_paused = true,
+            // The help, the screen and quitting are up to whoever runs the metronome
+            Help | Clear | Quit => {}
             SetTempo(tempo_str) => match tempo_str.parse::<i32>() {
                 Ok(tempo) if is_tempo_valid(tempo) => {
+                    self.tempo_ramp = None;
                     self.set_tempo(tempo);
                 }
-                _ => println!(
-                    "Invalid tempo `{}`! Must be a valid whole number between {}-{}!",
-                    tempo_str, TEMPO_MIN, TEMPO_MAX
-                ),
+                _ => {
+                    return Err(anyhow!(
+                        "Invalid tempo `{}`! Must be a valid whole number between {}-{}!",
+                        tempo_str,
+                        TEMPO_MIN,
+                        TEMPO_MAX
+                    ));
+                }
             },
-            SetTempoDirect(tempo) => self.set_tempo(*tempo),
+            // Unlike setting the tempo, nudging it keeps the metronome's place in the bar
+            NudgeTempo(change) => {
+                self.tempo_ramp = None;
+                self.tempo = (self.tempo + change).clamp(TEMPO_MIN, TEMPO_MAX);
+                self.recalculate_duration_per_subdivided_beat();
+            }
             SetTimeSignature(time_signature_str) => {
                 match time_signature_str.parse::<TimeSignature>() {
                     Ok(time_signature) => self.set_time_signature(time_signature),
-                    Err(_) => println!("Invalid time signature `{}`!", time_signature_str),
+                    Err(_) => {
+                        return Err(anyhow!("Invalid time signature `{}`!", time_signature_str));
+                    }
                 }
             }
             SetTempoType(tempo_type_str) => match tempo_type_str.parse::<TempoType>() {
                 Ok(tempo_type) => self.set_tempo_type(tempo_type),
-                Err(_) => println!("Invalid tempo type {}!", tempo_type_str),
+                Err(_) => return Err(anyhow!("Invalid tempo type {}!", tempo_type_str)),
             },
             SetSubdivision(subdivision_str) => match subdivision_str.parse::<i32>() {
                 Ok(s) if is_subdivision_valid(s) => self.set_subdivision(s.max(1)),
-                _ => {
-                    println!("Invalid subdivision {}!", subdivision_str)
-                }
+                _ => return Err(anyhow!("Invalid subdivision {}!", subdivision_str)),
             },
             SetSubdivisionSetting(subdivision_setting_str) => {
                 match subdivision_setting_str.parse::<SubdivisionSetting>() {
                     Ok(subdivision_setting) => self.subdivision_setting = subdivision_setting,
-                    Err(err) => println!(
-                        "Invalid subdivision setting \"{}\"! (Error: {})",
-                        subdivision_setting_str, err
-                    ),
+                    Err(err) => {
+                        return Err(anyhow!(
+                            "Invalid subdivision setting \"{}\"! (Error: {})",
+                            subdivision_setting_str,
+                            err
+                        ));
+                    }
+                }
+            }
+            SetAccents(accent_pattern_str) if accent_pattern_str.is_empty() => {
+                self.reset_beat_accents()
+            }
+            SetAccents(accent_pattern_str) => {
+                let result = accent_pattern_str
+                    .parse::<AccentPattern>()
+                    .and_then(|accent_pattern| self.set_beat_accents(accent_pattern));
+
+                if let Err(err) = result {
+                    return Err(anyhow!(
+                        "Invalid accent pattern \"{}\"! (Error: {})",
+                        accent_pattern_str,
+                        err
+                    ));
+                }
+            }
+            SetTempoRamp(tempo_ramp_str) if matches!(tempo_ramp_str.as_str(), "" | "stop") => {
+                self.tempo_ramp = None
+            }
+            SetTempoRamp(tempo_ramp_str) => match tempo_ramp_str.parse::<TempoRamp>() {
+                Ok(tempo_ramp) => {
+                    self.program = None;
+                    self.set_tempo(tempo_ramp.start_tempo());
+                    self.tempo_ramp = Some(tempo_ramp);
+                }
+                Err(err) => {
+                    return Err(anyhow!(
+                        "Invalid ramp \"{}\"! (Error: {})",
+                        tempo_ramp_str,
+                        err
+                    ));
+                }
+            },
+            SetVolume(volume_str) => {
+                if let Err(err) = self.volume.set_from_str(volume_str) {
+                    return Err(anyhow!(
+                        "Invalid volume \"{}\"! (Error: {})",
+                        volume_str,
+                        err
+                    ));
+                }
+            }
+            SetPolyrhythm(polyrhythm_str) if matches!(polyrhythm_str.as_str(), "" | "off") => {
+                self.polyrhythm = None
+            }
+            SetPolyrhythm(polyrhythm_str) => match polyrhythm_str.parse::<PolyrhythmLayer>() {
+                Ok(polyrhythm) => self.polyrhythm = Some(polyrhythm),
+                Err(err) => {
+                    return Err(anyhow!(
+                        "Invalid polyrhythm \"{}\"! (Error: {})",
+                        polyrhythm_str,
+                        err
+                    ));
+                }
+            },
+            SetGapClick(gap_click_str) if matches!(gap_click_str.as_str(), "" | "off") => {
+                self.gap_click = None
+            }
+            SetGapClick(gap_click_str) => match gap_click_str.parse::<GapClick>() {
+                Ok(gap_click) => self.gap_click = Some(gap_click),
+                Err(err) => {
+                    return Err(anyhow!(
+                        "Invalid gap \"{}\"! (Error: {})",
+                        gap_click_str,
+                        err
+                    ));
+                }
+            },
+            SetDropout(dropout_str) if matches!(dropout_str.as_str(), "" | "off") => {
+                self.dropout = None
+            }
+            SetDropout(dropout_str) => match dropout_str.parse::<Dropout>() {
+                Ok(dropout) => self.dropout = Some(dropout),
+                Err(err) => {
+                    return Err(anyhow!(
+                        "Invalid dropout \"{}\"! (Error: {})",
+                        dropout_str,
+                        err
+                    ));
+                }
+            },
+            LoadProgram(path) => self.load_program_file(Path::new(path))?,
+            StopProgram => self.program = None,
+            // Sounds belong to the audio engine, which handles this itself
+            SetSound(_, _) => {}
+            GotoSection(name) => match self.program {
+                Some(ref mut program) => program.goto(name)?,
+                None => return Err(anyhow!("No program is playing!")),
+            },
+            LoopSection(name) => match self.program {
+                Some(ref mut program) => {
+                    let name = (!matches!(name.as_str(), "" | "off")).then_some(name.as_str());
+                    program.set_loop(name)?;
+                }
+                None => return Err(anyhow!("No program is playing!")),
+            },
+            Tap(taps_per_beat_str) => {
+                let taps_per_beat = match taps_per_beat_str.as_str() {
+                    "" => Ok(1),
+                    _ => taps_per_beat_str.parse::<i32>(),
+                };
+
+                match taps_per_beat {
+                    Ok(taps_per_beat) if (1..=SUBDIVISION_MAX).contains(&taps_per_beat) => {
+                        let summary = self.stop_tap_along();
+                        self.tempo_measurer.clear();
+                        self.tempo_measurer.set_taps_per_beat(taps_per_beat as u32);
+                        self.tap_mode = true;
+                        self.is_paused = true;
+
+                        return Ok(summary);
+                    }
+                    _ => {
+                        return Err(anyhow!(
+                            "Invalid taps per beat `{}`! Must be a whole number between 1-{}!",
+                            taps_per_beat_str,
+                            SUBDIVISION_MAX
+                        ));
+                    }
                 }
             }
-            Tap => {
-                self.tap_mode = true;
-                self.is_paused = true;
+            StopTap if self.tap_mode => {
+                self.tap_mode = false;
+                self.is_paused = false;
+                self.beat_info.reset();
             }
-            Quit => {
-                println!("Goodbye!");
-                process::exit(0);
+            StopTap => return Err(anyhow!("Tap mode isn't on!")),
+            TapBeat(timestamp) if self.tap_mode => {
+                self.tempo_measurer.tap(*timestamp);
+
+                if self.tempo_measurer.num_tapped() >= TAPS_NEEDED
+                    && let Some(tempo) = self.tempo_measurer.calculate_tempo()
+                {
+                    self.tempo_ramp = None;
+                    self.set_tempo(tempo);
+                }
             }
-            Unknown(command) => {
-                println!("Unknown command `{}`!", command);
+            // Taps along with the click are compared with the beat grid, which only
+            // the beat tracker knows about
+            TapBeat(_) => {}
+            StartTapAlong if self.is_paused => {
+                return Err(anyhow!("The metronome has to be playing to tap along!"));
             }
-            SetTimer(duration) => match Timer::from_str(duration) {
-                Ok(t) => self.timer = Some(t),
+            StartTapAlong => self.tap_along = Some(TapAlong::default()),
+            StopTapAlong => return Ok(self.stop_tap_along()),
+            Unknown(command) => return Err(anyhow!("Unknown command `{}`!", command)),
+            SetTimer(duration) => match parse_duration(duration) {
+                Ok(duration) => self.timer = Some(Timer::start(duration, self.clock())),
                 Err(_) => {
-                    println!("Invalid timer string! Format: HH:MM:SS, hours optional")
+                    return Err(anyhow!(
+                        "Invalid timer string! Format: HH:MM:SS, hours optional"
+                    ));
                 }
             },
             StopTimer => self.timer = None,
+            // The settings are written by the Ui
+            SendStatus(_) => {}
         };
 
-        if self.is_paused {
-            println!("PAUSED!");
-        }
-        if self.tap_mode {
-            println!("TAP MODE. Press enter for each beat. Enter `q` to exit.");
-        }
+        Ok(None)
     }
 }
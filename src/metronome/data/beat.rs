@@ -4,6 +4,7 @@ use std::{
 };
 
 use super::MetronomeData;
+use crate::{clock::SharedClock, metronome::sound::AUDIO_LATENCY};
 
 pub mod accent;
 
@@ -47,51 +48,130 @@ impl BeatInfo {
     }
 }
 
-pub struct MetronomeBeatTracker {
-    metronome_data: Arc<RwLock<MetronomeData>>,
-    last_beat_timestamp: Instant,
+/// The pulses of the polyrhythm layer over one span, which starts on a beat of the
+/// main layer
+struct PolyrhythmPulses {
+    span_start: Instant,
+    span: Duration,
+    pulses: u32,
+    pulses_played: u32,
 }
 
-fn instant_now_offset() -> Instant {
-    const DURATION_OFFSET: Duration = Duration::from_secs(100);
-    Instant::now() - DURATION_OFFSET
+/// Keeps track of when each subdivided beat should be played. Every beat is placed
+/// on a grid that starts at `grid_start`, so being woken up late never pushes the
+/// beats after it back
+pub struct MetronomeBeatTracker {
+    metronome_data: Arc<RwLock<MetronomeData>>,
+    clock: SharedClock,
+    grid_start: Instant,
+    beats_played: u32,
+    duration_per_subdivided_beat: Duration,
+    polyrhythm_pulses: Option<PolyrhythmPulses>,
 }
 
 impl MetronomeBeatTracker {
-    pub fn offset_beat_timestamp(&mut self) {
-        self.last_beat_timestamp = instant_now_offset();
-    }
+    pub fn new(metronome_data: Arc<RwLock<MetronomeData>>, clock: SharedClock) -> Self {
+        let duration_per_subdivided_beat =
+            metronome_data.read().unwrap().duration_per_subdivided_beat;
 
-    pub fn new(metronome_data: Arc<RwLock<MetronomeData>>) -> Self {
         Self {
             metronome_data,
-            last_beat_timestamp: instant_now_offset(),
+            grid_start: clock.now(),
+            clock,
+            beats_played: 0,
+            duration_per_subdivided_beat,
+            polyrhythm_pulses: None,
         }
     }
 
+    /// Starts a new grid, with the next beat falling on now
+    pub fn restart(&mut self) {
+        self.grid_start = self.clock.now();
+        self.beats_played = 0;
+        self.polyrhythm_pulses = None;
+    }
+
     pub fn move_to_next_subdivided_beat(&mut self) {
-        let MetronomeData {
-            time_signature,
-            subdivision,
-            ..
-        } = *self.metronome_data.read().unwrap();
+        let beat_timestamp =
+            self.grid_start + self.duration_per_subdivided_beat * self.beats_played;
+
+        let mut metronome_data = self.metronome_data.write().unwrap();
+        metronome_data.move_to_next_subdivided_beat();
+        self.beats_played += 1;
+
+        if let Some((polyrhythm, span)) = metronome_data.polyrhythm_span() {
+            self.polyrhythm_pulses = Some(PolyrhythmPulses {
+                span_start: beat_timestamp,
+                span,
+                pulses: polyrhythm.pulses as u32,
+                pulses_played: 0,
+            });
+        }
+    }
 
-        self.metronome_data
-            .write()
-            .unwrap()
-            .beat_info
-            .next_subdivided_beat(time_signature.0, subdivision);
-        self.last_beat_timestamp = Instant::now();
+    /// The instant the next pulse of the polyrhythm layer falls on, or `None` if
+    /// there's no polyrhythm or every pulse of the current span has been played
+    pub fn next_polyrhythm_timestamp(&self) -> Option<Instant> {
+        // The layer might have been turned off in the middle of a span
+        self.metronome_data.read().unwrap().polyrhythm?;
+
+        let polyrhythm_pulses = self.polyrhythm_pulses.as_ref()?;
+
+        (polyrhythm_pulses.pulses_played < polyrhythm_pulses.pulses).then(|| {
+            polyrhythm_pulses.span_start
+                + polyrhythm_pulses.span * polyrhythm_pulses.pulses_played
+                    / polyrhythm_pulses.pulses
+        })
+    }
+
+    pub fn move_to_next_polyrhythm_pulse(&mut self) {
+        if let Some(ref mut polyrhythm_pulses) = self.polyrhythm_pulses {
+            polyrhythm_pulses.pulses_played += 1;
+        }
     }
 
-    pub fn should_play_beat(&self) -> bool {
+    /// The instant the next subdivided beat falls on. If the tempo changed, the grid
+    /// continues from the last beat played with the new tempo, and if the metronome
+    /// fell more than a beat behind, the grid starts over from now
+    pub fn next_beat_timestamp(&mut self) -> Instant {
         let duration_per_subdivided_beat = self
             .metronome_data
             .read()
             .unwrap()
             .duration_per_subdivided_beat;
 
-        self.last_beat_timestamp.elapsed() >= duration_per_subdivided_beat
+        if duration_per_subdivided_beat != self.duration_per_subdivided_beat {
+            if self.beats_played > 0 {
+                self.grid_start += self.duration_per_subdivided_beat * (self.beats_played - 1);
+                self.beats_played = 1;
+            }
+            self.duration_per_subdivided_beat = duration_per_subdivided_beat;
+        }
+
+        let next_beat_timestamp =
+            self.grid_start + self.duration_per_subdivided_beat * self.beats_played;
+
+        if next_beat_timestamp + self.duration_per_subdivided_beat < self.clock.now() {
+            self.restart();
+            return self.grid_start;
+        }
+
+        next_beat_timestamp
+    }
+
+    /// How far `timestamp` is from the nearest subdivided beat as it's heard, in
+    /// milliseconds. Negative is early and positive is late
+    pub fn offset_from_nearest_beat(&self, timestamp: Instant) -> f64 {
+        let heard_grid_start = self.grid_start + AUDIO_LATENCY;
+        let since_grid_start = if timestamp >= heard_grid_start {
+            (timestamp - heard_grid_start).as_secs_f64()
+        } else {
+            -(heard_grid_start - timestamp).as_secs_f64()
+        };
+        let beat_secs = self.duration_per_subdivided_beat.as_secs_f64();
+        let nearest_beat = (since_grid_start / beat_secs).round().max(0.0);
+
+        (since_grid_start - nearest_beat * beat_secs) * 1000.0
     }
 
     pub fn is_downbeat(&self) -> bool {
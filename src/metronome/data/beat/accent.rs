@@ -1,12 +1,75 @@
-use crate::metronome::data::TimeSignature;
+use anyhow::anyhow;
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+use crate::metronome::data::{TimeSignature, beat_grouping::BeatGrouping};
 
 use super::BeatInfo;
 
-#[derive(Debug, Clone, Copy)]
+/// The character used for each `MetronomeBeatAccent`, both in accent patterns and
+/// in the UI
+pub const BEAT_ACCENT_CHAR: [char; 4] = ['X', 'x', '.', 'o'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum MetronomeBeatAccent {
     Accented = 0,
     Beat,
     Subdivision,
+    /// A pulse of the polyrhythm layer, which can't be used in accent patterns
+    Polyrhythm,
+}
+
+impl MetronomeBeatAccent {
+    const ALL: [Self; 3] = [Self::Accented, Self::Beat, Self::Subdivision];
+
+    pub fn to_char(self) -> char {
+        BEAT_ACCENT_CHAR[self as usize]
+    }
+}
+
+/// An accent for every beat in the measure, written as a string like `X.x.xx.`, where
+/// "X" is accented, "x" is a normal beat and "." is played like a subdivision
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccentPattern(pub Vec<MetronomeBeatAccent>);
+
+impl FromStr for AccentPattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(anyhow!("Accent pattern cannot be empty!"));
+        }
+
+        let beat_accents = s
+            .chars()
+            .map(|c| {
+                MetronomeBeatAccent::ALL
+                    .into_iter()
+                    .find(|accent| accent.to_char() == c)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Accent pattern can only contain \"{}\", \"{}\" or \"{}\"!",
+                            BEAT_ACCENT_CHAR[0],
+                            BEAT_ACCENT_CHAR[1],
+                            BEAT_ACCENT_CHAR[2]
+                        )
+                    })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self(beat_accents))
+    }
+}
+
+impl fmt::Display for AccentPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for accent in self.0.iter().copied() {
+            write!(f, "{}", accent.to_char())?;
+        }
+
+        Ok(())
+    }
 }
 
 pub fn get_metronome_beat_accent(
@@ -20,30 +83,26 @@ pub fn get_metronome_beat_accent(
     }
 }
 
-fn eighths_beat_accents(time_signature: TimeSignature) -> Vec<MetronomeBeatAccent> {
-    let mut beat_accents = vec![MetronomeBeatAccent::Subdivision; time_signature.0 as usize];
-    beat_accents[0] = MetronomeBeatAccent::Accented;
-
-    match time_signature.0 {
-        7 => {
-            beat_accents[3] = MetronomeBeatAccent::Beat;
-            beat_accents[5] = MetronomeBeatAccent::Beat;
-        }
-        _ => {
-            for i in (0..time_signature.0).step_by(3).skip(1) {
-                beat_accents[i as usize] = MetronomeBeatAccent::Beat;
+fn grouped_beat_accents(beat_grouping: BeatGrouping) -> Vec<MetronomeBeatAccent> {
+    let mut beat_accents: Vec<MetronomeBeatAccent> = (0..beat_grouping.num_beats())
+        .map(|beat| {
+            if beat_grouping.is_group_start(beat) {
+                MetronomeBeatAccent::Beat
+            } else {
+                MetronomeBeatAccent::Subdivision
             }
-        }
-    }
+        })
+        .collect();
+    beat_accents[0] = MetronomeBeatAccent::Accented;
     beat_accents
 }
 
 pub fn get_beat_accents_from_time_signature(
     time_signature: TimeSignature,
 ) -> Vec<MetronomeBeatAccent> {
-    match time_signature.1 {
-        8 => eighths_beat_accents(time_signature),
-        _ => {
+    match time_signature.beat_grouping() {
+        Some(beat_grouping) => grouped_beat_accents(beat_grouping),
+        None => {
             let mut beat_accents = vec![MetronomeBeatAccent::Beat; time_signature.0 as usize];
             beat_accents[0] = MetronomeBeatAccent::Accented;
             beat_accents
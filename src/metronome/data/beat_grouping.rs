@@ -0,0 +1,166 @@
+use anyhow::anyhow;
+use std::{fmt, str::FromStr};
+
+/// The most beats a grouping can span, since each beat is one bit
+pub const MAX_GROUPED_BEATS: i32 = u64::BITS as i32;
+
+/// How the beats of a measure are grouped together, like `2+2+3` for a `7/8`. Stored
+/// as a bit for every beat, which is set if a group starts on that beat
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BeatGrouping {
+    group_starts: u64,
+    num_beats: i32,
+}
+
+impl BeatGrouping {
+    /// The grouping used for time signatures that end in eighths when no grouping
+    /// is given. It's groups of 3, except for `7/8`, which is `3+2+2`
+    pub fn default_for_eighths(num_beats: i32) -> Self {
+        let group_sizes = match num_beats {
+            7 => vec![3, 2, 2],
+            _ => (0..num_beats)
+                .step_by(3)
+                .map(|start| (num_beats - start).min(3))
+                .collect(),
+        };
+
+        Self::from_group_sizes(&group_sizes)
+    }
+
+    fn from_group_sizes(group_sizes: &[i32]) -> Self {
+        let mut group_starts = 0;
+        let mut num_beats = 0;
+
+        for group_size in group_sizes {
+            group_starts |= 1 << num_beats;
+            num_beats += group_size;
+        }
+
+        Self {
+            group_starts,
+            num_beats,
+        }
+    }
+
+    pub fn num_beats(&self) -> i32 {
+        self.num_beats
+    }
+
+    pub fn is_group_start(&self, beat: i32) -> bool {
+        self.group_starts & (1 << beat) != 0
+    }
+
+    /// How far `beat` is from the start of its group, with the first beat of a group
+    /// being 0
+    pub fn position_in_group(&self, beat: i32) -> i32 {
+        (0..=beat)
+            .rev()
+            .find(|&b| self.is_group_start(b))
+            .map(|group_start| beat - group_start)
+            .unwrap_or(beat)
+    }
+
+    pub fn group_sizes(&self) -> Vec<i32> {
+        let mut group_sizes: Vec<i32> = Vec::new();
+
+        for beat in 0..self.num_beats {
+            match group_sizes.last_mut() {
+                Some(group_size) if !self.is_group_start(beat) => *group_size += 1,
+                _ => group_sizes.push(1),
+            }
+        }
+
+        group_sizes
+    }
+}
+
+impl FromStr for BeatGrouping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let group_sizes = s
+            .split('+')
+            .map(|group_size| match group_size.trim().parse::<i32>() {
+                Ok(group_size) if group_size >= 1 => Ok(group_size),
+                _ => Err(anyhow!(
+                    "Invalid beat grouping `{}`! Example beat grouping: 2+2+3",
+                    s
+                )),
+            })
+            .collect::<anyhow::Result<Vec<i32>>>()?;
+
+        if group_sizes.iter().sum::<i32>() > MAX_GROUPED_BEATS {
+            return Err(anyhow!(
+                "A beat grouping cannot have more than {} beats!",
+                MAX_GROUPED_BEATS
+            ));
+        }
+
+        Ok(Self::from_group_sizes(&group_sizes))
+    }
+}
+
+impl fmt::Display for BeatGrouping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let group_sizes = self.group_sizes();
+
+        for (i, group_size) in group_sizes.iter().enumerate() {
+            if i > 0 {
+                write!(f, "+")?;
+            }
+            write!(f, "{}", group_size)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metronome::data::TimeSignature;
+    use crate::metronome::data::beat_grouping::BeatGrouping;
+    use std::str::FromStr;
+
+    #[test]
+    fn additive_time_signature_from_str() {
+        let time_signature = TimeSignature::from_str("2+2+3/8").unwrap();
+        let beat_grouping = time_signature.beat_grouping().unwrap();
+
+        assert_eq!(time_signature.0, 7);
+        assert_eq!(beat_grouping.group_sizes(), [2, 2, 3]);
+        assert_eq!(time_signature.to_string(), "7/8 (2+2+3)");
+        assert_eq!(time_signature.notation(), "2+2+3/8");
+        assert_eq!(
+            TimeSignature::from_str(&time_signature.notation()).unwrap(),
+            time_signature
+        );
+
+        let positions: Vec<i32> = (0..7).map(|b| beat_grouping.position_in_group(b)).collect();
+        assert_eq!(positions, [0, 1, 0, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn default_eighths_grouping() {
+        assert_eq!(BeatGrouping::default_for_eighths(6).group_sizes(), [3, 3]);
+        assert_eq!(
+            BeatGrouping::default_for_eighths(7).group_sizes(),
+            [3, 2, 2]
+        );
+        assert_eq!(
+            BeatGrouping::default_for_eighths(8).group_sizes(),
+            [3, 3, 2]
+        );
+        assert_eq!(
+            TimeSignature::from_str("4/4").unwrap().beat_grouping(),
+            None
+        );
+    }
+
+    #[test]
+    fn invalid_beat_grouping() {
+        assert!(TimeSignature::from_str("2+/8").is_err());
+        assert!(TimeSignature::from_str("2+0+3/8").is_err());
+        assert!(TimeSignature::from_str("2+x/8").is_err());
+        assert!(TimeSignature::from_str("60+10/8").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::anyhow;
+use std::{
+    fmt,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Random beat dropout, which mutes each click with a chance of `percent`. The same
+/// seed always drops the same beats, so an exercise can be repeated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dropout {
+    percent: u8,
+    keep_downbeats: bool,
+    seed: u64,
+    rng_state: u64,
+    is_dropped: bool,
+    num_clicks: u32,
+    num_dropped: u32,
+}
+
+impl Dropout {
+    /// A splitmix64 generator, which is plenty for deciding which beats to drop and
+    /// works with any seed
+    fn next_random_percent(&mut self) -> u64 {
+        self.rng_state = self.rng_state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (z ^ (z >> 31)) % 100
+    }
+
+    /// Decides whether the click of the next subdivided beat is dropped. Beats without
+    /// a click are never counted
+    pub fn next_beat(&mut self, has_click: bool, is_downbeat: bool) {
+        self.is_dropped = false;
+
+        if !has_click {
+            return;
+        }
+
+        self.num_clicks += 1;
+
+        // The generator moves on for every click, so keeping the downbeats doesn't
+        // change which of the other beats are dropped
+        let random_percent = self.next_random_percent();
+        if is_downbeat && self.keep_downbeats {
+            return;
+        }
+
+        self.is_dropped = random_percent < self.percent as u64;
+        if self.is_dropped {
+            self.num_dropped += 1;
+        }
+    }
+
+    /// Whether the click of the current subdivided beat is dropped
+    pub fn is_dropped(&self) -> bool {
+        self.is_dropped
+    }
+}
+
+impl FromStr for Dropout {
+    type Err = anyhow::Error;
+
+    /// Parses a dropout with the format of `<PERCENT> [keep-downbeats] [seed <SEED>]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut args = s.split_whitespace();
+
+        let percent_str = args.next().unwrap_or_default();
+        let percent = match percent_str.trim_end_matches('%').parse::<u8>() {
+            Ok(percent) if percent <= 100 => percent,
+            _ => {
+                return Err(anyhow!(
+                    "Dropout chance must be a whole number between 0-100! Example dropout: `30 keep-downbeats seed 7`"
+                ));
+            }
+        };
+
+        let mut keep_downbeats = false;
+        let mut seed = None;
+
+        while let Some(arg) = args.next() {
+            match arg {
+                "keep-downbeats" => keep_downbeats = true,
+                "seed" => {
+                    let seed_str = args.next().unwrap_or_default();
+                    seed = Some(
+                        seed_str
+                            .parse::<u64>()
+                            .map_err(|_| anyhow!("Invalid seed `{}`!", seed_str))?,
+                    );
+                }
+                _ => return Err(anyhow!("Unknown dropout option `{}`!", arg)),
+            }
+        }
+
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as u64
+                % 1_000_000
+        });
+
+        Ok(Self {
+            percent,
+            keep_downbeats,
+            seed,
+            rng_state: seed,
+            is_dropped: false,
+            num_clicks: 0,
+            num_dropped: 0,
+        })
+    }
+}
+
+impl fmt::Display for Dropout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.percent)?;
+
+        if self.keep_downbeats {
+            write!(f, " keep-downbeats")?;
+        }
+
+        write!(
+            f,
+            " seed {} (dropped {} of {} clicks)",
+            self.seed, self.num_dropped, self.num_clicks
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metronome::data::dropout::Dropout;
+    use std::str::FromStr;
+
+    fn dropped_beats(dropout: &mut Dropout, beats_per_bar: usize, bars: usize) -> Vec<bool> {
+        (0..beats_per_bar * bars)
+            .map(|beat| {
+                dropout.next_beat(true, beat % beats_per_bar == 0);
+                dropout.is_dropped()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seed_repeats_the_same_dropout() {
+        let mut dropout = Dropout::from_str("50 seed 7").unwrap();
+        let dropped = dropped_beats(&mut dropout, 4, 8);
+
+        assert_eq!(
+            dropped,
+            dropped_beats(&mut Dropout::from_str("50 seed 7").unwrap(), 4, 8)
+        );
+        assert!(dropped.contains(&true) && dropped.contains(&false));
+
+        let num_dropped = dropped.iter().filter(|is_dropped| **is_dropped).count();
+        assert_eq!(
+            dropout.to_string(),
+            format!("50% seed 7 (dropped {} of 32 clicks)", num_dropped)
+        );
+    }
+
+    #[test]
+    fn downbeats_can_be_kept() {
+        let mut dropout = Dropout::from_str("100 keep-downbeats").unwrap();
+        let dropped = dropped_beats(&mut dropout, 3, 2);
+
+        assert_eq!(dropped, [false, true, true, false, true, true]);
+
+        // Beats without a click aren't counted
+        dropout.next_beat(false, false);
+        assert!(!dropout.is_dropped());
+        assert!(dropout.to_string().ends_with("(dropped 4 of 6 clicks)"));
+    }
+
+    #[test]
+    fn invalid_dropout() {
+        assert!(Dropout::from_str("").is_err());
+        assert!(Dropout::from_str("101").is_err());
+        assert!(Dropout::from_str("30 seed").is_err());
+        assert!(Dropout::from_str("30 downbeats").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::anyhow;
+use std::{fmt, str::FromStr};
+
+/// Gap-click training, which plays the click for `play_bars` bars and then silences
+/// it for `mute_bars` bars. The beats keep going while it's silent, so the click comes
+/// back exactly where it would have been
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GapClick {
+    play_bars: u32,
+    mute_bars: u32,
+    bars_started: u32,
+}
+
+impl GapClick {
+    /// Where the current bar is in the play-then-mute cycle, with 0 being the first
+    /// bar played
+    fn cycle_bar(&self) -> u32 {
+        self.bars_started.saturating_sub(1) % (self.play_bars + self.mute_bars)
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.cycle_bar() >= self.play_bars
+    }
+
+    pub fn next_bar(&mut self) {
+        self.bars_started += 1;
+    }
+}
+
+impl FromStr for GapClick {
+    type Err = anyhow::Error;
+
+    /// Parses a gap with the format of `<PLAY BARS> <MUTE BARS>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let args: Vec<&str> = s.split_whitespace().collect();
+
+        let [play_bars_str, mute_bars_str] = args.as_slice() else {
+            return Err(anyhow!(
+                "Invalid gap! Example gap: `4 2` plays 4 bars, then mutes 2"
+            ));
+        };
+
+        let play_bars: u32 = play_bars_str.parse()?;
+        let mute_bars: u32 = mute_bars_str.parse()?;
+
+        if play_bars < 1 || mute_bars < 1 {
+            return Err(anyhow!("Gap must play and mute at least 1 bar!"));
+        }
+
+        Ok(Self {
+            play_bars,
+            mute_bars,
+            bars_started: 0,
+        })
+    }
+}
+
+impl fmt::Display for GapClick {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cycle_bar = self.cycle_bar();
+
+        write!(f, "play {}, mute {} (", self.play_bars, self.mute_bars)?;
+
+        if self.is_muted() {
+            write!(
+                f,
+                "silent bar {}/{})",
+                cycle_bar - self.play_bars + 1,
+                self.mute_bars
+            )
+        } else {
+            write!(f, "bar {}/{})", cycle_bar + 1, self.play_bars)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metronome::data::gap_click::GapClick;
+    use std::str::FromStr;
+
+    #[test]
+    fn gap_mutes_bars_after_playing() {
+        let mut gap_click = GapClick::from_str("2 1").unwrap();
+
+        let muted: Vec<bool> = (0..6)
+            .map(|_| {
+                gap_click.next_bar();
+                gap_click.is_muted()
+            })
+            .collect();
+        assert_eq!(muted, [false, false, true, false, false, true]);
+        assert_eq!(gap_click.to_string(), "play 2, mute 1 (silent bar 1/1)");
+
+        assert!(GapClick::from_str("4").is_err());
+        assert!(GapClick::from_str("4 0").is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::anyhow;
+use std::{fmt, str::FromStr};
+
+pub const POLYRHYTHM_PULSES_MIN: i32 = 2;
+pub const POLYRHYTHM_PULSES_MAX: i32 = 16;
+
+/// How far each layer is panned to its side when panning is on, from 0 (center) to
+/// 1 (all the way)
+pub const POLYRHYTHM_PAN: f32 = 0.7;
+
+fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// What a polyrhythm layer divides into its pulses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolyrhythmScope {
+    Beat,
+    Bar,
+}
+
+/// A second rhythmic layer that divides every beat, or the whole bar, into a
+/// different number of pulses than the main subdivision
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PolyrhythmLayer {
+    pub pulses: i32,
+    pub scope: PolyrhythmScope,
+    /// Whether the main layer is panned to the left and the polyrhythm to the right
+    pub pan: bool,
+}
+
+impl PolyrhythmLayer {
+    /// How many pulses of the main layer there are in one span of the polyrhythm
+    pub fn main_pulses(&self, num_beats: i32, subdivision: i32) -> i32 {
+        match self.scope {
+            PolyrhythmScope::Beat => subdivision,
+            PolyrhythmScope::Bar => num_beats * subdivision,
+        }
+    }
+
+    /// The number of cells needed to line up both layers on one grid
+    pub fn grid_cells(&self, main_pulses: i32) -> i32 {
+        self.pulses / gcd(self.pulses, main_pulses) * main_pulses
+    }
+}
+
+impl FromStr for PolyrhythmLayer {
+    type Err = anyhow::Error;
+
+    /// Parses a layer with the format of `<PULSES> [beat | bar] [pan]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut args = s.split_whitespace();
+
+        let pulses: i32 = args
+            .next()
+            .ok_or_else(|| anyhow!("Missing the number of pulses!"))?
+            .parse()?;
+
+        if !(POLYRHYTHM_PULSES_MIN..=POLYRHYTHM_PULSES_MAX).contains(&pulses) {
+            return Err(anyhow!(
+                "Number of pulses must be between {}-{}!",
+                POLYRHYTHM_PULSES_MIN,
+                POLYRHYTHM_PULSES_MAX
+            ));
+        }
+
+        let mut polyrhythm_layer = Self {
+            pulses,
+            scope: PolyrhythmScope::Beat,
+            pan: false,
+        };
+
+        for arg in args {
+            match arg {
+                "beat" => polyrhythm_layer.scope = PolyrhythmScope::Beat,
+                "bar" => polyrhythm_layer.scope = PolyrhythmScope::Bar,
+                "pan" => polyrhythm_layer.pan = true,
+                _ => return Err(anyhow!("Unknown polyrhythm option `{}`!", arg)),
+            }
+        }
+
+        Ok(polyrhythm_layer)
+    }
+}
+
+impl fmt::Display for PolyrhythmLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} per {}",
+            self.pulses,
+            match self.scope {
+                PolyrhythmScope::Beat => "beat",
+                PolyrhythmScope::Bar => "bar",
+            }
+        )?;
+
+        if self.pan {
+            write!(f, ", panned")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metronome::data::polyrhythm::{PolyrhythmLayer, PolyrhythmScope};
+    use std::str::FromStr;
+
+    #[test]
+    fn polyrhythm_layer_from_str() {
+        let polyrhythm_layer = PolyrhythmLayer::from_str("5 bar pan").unwrap();
+
+        assert_eq!(polyrhythm_layer.pulses, 5);
+        assert_eq!(polyrhythm_layer.scope, PolyrhythmScope::Bar);
+        assert!(polyrhythm_layer.pan);
+        assert_eq!(polyrhythm_layer.main_pulses(4, 1), 4);
+        assert_eq!(polyrhythm_layer.grid_cells(4), 20);
+
+        let polyrhythm_layer = PolyrhythmLayer::from_str("3").unwrap();
+        assert_eq!(polyrhythm_layer.main_pulses(4, 2), 2);
+        assert_eq!(polyrhythm_layer.grid_cells(2), 6);
+        assert_eq!(polyrhythm_layer.to_string(), "3 per beat");
+    }
+
+    #[test]
+    fn invalid_polyrhythm_layer() {
+        assert!(PolyrhythmLayer::from_str("").is_err());
+        assert!(PolyrhythmLayer::from_str("1").is_err());
+        assert!(PolyrhythmLayer::from_str("17").is_err());
+        assert!(PolyrhythmLayer::from_str("3 measure").is_err());
+    }
+}
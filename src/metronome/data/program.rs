@@ -0,0 +1,364 @@
+use anyhow::{Context, anyhow};
+use serde::Deserialize;
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use super::{
+    TempoType, TimeSignature, is_subdivision_valid, is_tempo_valid,
+    subdivision_setting::SubdivisionSetting,
+};
+
+mod midi_map;
+mod music_xml;
+
+/// A number of bars that are all played with the same settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub name: Option<String>,
+    pub bars: u32,
+    pub tempo: i32,
+    pub time_signature: TimeSignature,
+    pub tempo_type: Option<TempoType>,
+    pub subdivision: i32,
+    pub subdivision_setting: SubdivisionSetting,
+    /// The number of each bar in the score the section was read from, if any
+    pub measure_numbers: Vec<String>,
+}
+
+/// A section the way it's written in a program file, before any of it is validated
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SectionEntry {
+    name: Option<String>,
+    bars: u32,
+    tempo: i32,
+    time_signature: Option<String>,
+    tempo_type: Option<String>,
+    subdivision: Option<i32>,
+    subdivision_setting: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProgramFile {
+    section: Vec<SectionEntry>,
+}
+
+impl TryFrom<SectionEntry> for Section {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: SectionEntry) -> Result<Self, Self::Error> {
+        if entry.bars < 1 {
+            return Err(anyhow!("A section must have at least 1 bar!"));
+        }
+        if !is_tempo_valid(entry.tempo) {
+            return Err(anyhow!("Invalid tempo `{}`!", entry.tempo));
+        }
+
+        let subdivision = entry.subdivision.unwrap_or(1);
+        if !is_subdivision_valid(subdivision) {
+            return Err(anyhow!("Invalid subdivision `{}`!", subdivision));
+        }
+
+        Ok(Self {
+            name: entry.name,
+            bars: entry.bars,
+            tempo: entry.tempo,
+            time_signature: entry
+                .time_signature
+                .as_deref()
+                .map(TimeSignature::from_str)
+                .transpose()?
+                .unwrap_or_default(),
+            tempo_type: entry
+                .tempo_type
+                .as_deref()
+                .map(TempoType::from_str)
+                .transpose()?,
+            subdivision: subdivision.max(1),
+            subdivision_setting: entry
+                .subdivision_setting
+                .as_deref()
+                .map(SubdivisionSetting::from_str)
+                .transpose()?
+                .unwrap_or_default(),
+            measure_numbers: Vec::new(),
+        })
+    }
+}
+
+/// A song or exercise, described bar by bar as a list of sections
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub sections: Vec<Section>,
+}
+
+impl Program {
+    /// Loads a TOML program, the tempo map of a MIDI file if it ends in `.mid`, or the
+    /// measures of a MusicXML score if it ends in `.musicxml` or `.xml`
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "mid" | "midi" => {
+                let bytes = fs::read(path)
+                    .with_context(|| format!("Failed to read MIDI file `{}`", path.display()))?;
+
+                return Self::from_midi(&bytes)
+                    .with_context(|| format!("Invalid MIDI file `{}`", path.display()));
+            }
+            "musicxml" | "xml" => {
+                let xml = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read score `{}`", path.display()))?;
+
+                return Self::from_music_xml(&xml)
+                    .with_context(|| format!("Invalid score `{}`", path.display()));
+            }
+            "mxl" => {
+                return Err(anyhow!(
+                    "Compressed MusicXML isn't supported! Export `{}` as uncompressed .musicxml instead",
+                    path.display()
+                ));
+            }
+            _ => {}
+        }
+
+        let program_str = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read program `{}`", path.display()))?;
+
+        program_str
+            .parse()
+            .with_context(|| format!("Invalid program `{}`", path.display()))
+    }
+
+    /// Finds a section by its name, ignoring case
+    fn find_section(&self, name: &str) -> anyhow::Result<usize> {
+        self.sections
+            .iter()
+            .position(|section| {
+                section
+                    .name
+                    .as_ref()
+                    .is_some_and(|section_name| section_name.eq_ignore_ascii_case(name))
+            })
+            .ok_or_else(|| anyhow!("There's no section named `{}`!", name))
+    }
+}
+
+impl FromStr for Program {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let program_file: ProgramFile = toml::from_str(s)?;
+
+        let sections = program_file
+            .section
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                Section::try_from(entry).with_context(|| format!("Invalid section {}", i + 1))
+            })
+            .collect::<anyhow::Result<Vec<Section>>>()?;
+
+        if sections.is_empty() {
+            return Err(anyhow!("A program must have at least 1 section!"));
+        }
+
+        Ok(Self { sections })
+    }
+}
+
+/// Plays through a program, keeping track of which section and bar it's on
+#[derive(Debug, Clone)]
+pub struct ProgramPlayer {
+    program: Program,
+    section_index: usize,
+    bar_in_section: u32,
+    bar_number: u32,
+    looped_section: Option<usize>,
+    pending_jump: Option<usize>,
+}
+
+impl ProgramPlayer {
+    pub fn new(program: Program) -> Self {
+        Self {
+            program,
+            section_index: 0,
+            bar_in_section: 0,
+            bar_number: 0,
+            looped_section: None,
+            pending_jump: None,
+        }
+    }
+
+    pub fn current_section(&self) -> &Section {
+        &self.program.sections[self.section_index]
+    }
+
+    /// Moves to the next bar
+    pub fn next_bar(&mut self) -> NextBar<'_> {
+        self.bar_number += 1;
+
+        let next_section_index = if let Some(section_index) = self.pending_jump.take() {
+            section_index
+        } else if self.bar_in_section < self.current_section().bars {
+            self.bar_in_section += 1;
+            return NextBar::SameSection;
+        } else if self.looped_section == Some(self.section_index) {
+            self.section_index
+        } else if self.section_index + 1 < self.program.sections.len() {
+            self.section_index + 1
+        } else {
+            return NextBar::Finished;
+        };
+
+        self.section_index = next_section_index;
+        self.bar_in_section = 1;
+
+        NextBar::NewSection(self.current_section())
+    }
+
+    /// Jumps to the section named `name` at the start of the next bar
+    pub fn goto(&mut self, name: &str) -> anyhow::Result<()> {
+        self.pending_jump = Some(self.program.find_section(name)?);
+        Ok(())
+    }
+
+    /// Repeats the section named `name` until looping is turned off, jumping to it at
+    /// the start of the next bar. `None` turns looping off
+    pub fn set_loop(&mut self, name: Option<&str>) -> anyhow::Result<()> {
+        self.looped_section = match name {
+            Some(name) => {
+                let section_index = self.program.find_section(name)?;
+
+                if section_index != self.section_index {
+                    self.pending_jump = Some(section_index);
+                }
+                Some(section_index)
+            }
+            None => None,
+        };
+
+        Ok(())
+    }
+}
+
+/// What happens when a program moves to the next bar
+#[derive(Debug, Clone, Copy)]
+pub enum NextBar<'a> {
+    SameSection,
+    /// The bar starts a section, whose settings should be switched to
+    NewSection(&'a Section),
+    /// The last bar of the last section has already been played
+    Finished,
+}
+
+impl fmt::Display for ProgramPlayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let section = self.current_section();
+
+        match section.name {
+            Some(ref name) => write!(f, "Section {}", name)?,
+            None => write!(f, "Section {}", self.section_index + 1)?,
+        }
+
+        // Scores number their own measures, which might skip or repeat numbers
+        let bar_in_section = self.bar_in_section.max(1);
+        let bar_number = match section.measure_numbers.get(bar_in_section as usize - 1) {
+            Some(measure_number) => measure_number.clone(),
+            None => self.bar_number.max(1).to_string(),
+        };
+
+        write!(
+            f,
+            " (bar {}/{})\t\tBar {}",
+            bar_in_section, section.bars, bar_number
+        )?;
+
+        if self.looped_section == Some(self.section_index) {
+            write!(f, "\t\tLOOPING")?;
+        }
+
+        if let Some(section_index) = self.pending_jump {
+            match self.program.sections[section_index].name {
+                Some(ref name) => write!(f, "\t\tNext: {}", name)?,
+                None => write!(f, "\t\tNext: Section {}", section_index + 1)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metronome::data::program::{NextBar, Program, ProgramPlayer};
+    use std::str::FromStr;
+
+    const PROGRAM: &str = r#"
+        [[section]]
+        name = "A"
+        bars = 2
+        tempo = 120
+
+        [[section]]
+        name = "B"
+        bars = 1
+        tempo = 90
+        time_signature = "7/8"
+        subdivision_setting = "x-x"
+    "#;
+
+    /// The name of the section every bar is played in, until the program finishes
+    fn bar_sections(player: &mut ProgramPlayer, bars: usize) -> Vec<String> {
+        (0..bars)
+            .map_while(|_| {
+                if let NextBar::Finished = player.next_bar() {
+                    return None;
+                }
+                player.current_section().name.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn program_from_str() {
+        let program = Program::from_str(PROGRAM).unwrap();
+
+        assert_eq!(program.sections.len(), 2);
+        assert_eq!(program.sections[1].time_signature.to_string(), "7/8");
+        assert_eq!(program.sections[1].subdivision, 1);
+    }
+
+    #[test]
+    fn invalid_program() {
+        assert!(Program::from_str("").is_err());
+        assert!(Program::from_str("[[section]]\nbars = 2").is_err());
+        assert!(Program::from_str("[[section]]\nbars = 2\ntempo = 1000").is_err());
+        assert!(Program::from_str("[[section]]\nbars = 0\ntempo = 100").is_err());
+        assert!(Program::from_str("[[section]]\nbars = 1\ntempo = 100\nswing = 1").is_err());
+    }
+
+    #[test]
+    fn program_plays_sections_in_order() {
+        let mut player = ProgramPlayer::new(Program::from_str(PROGRAM).unwrap());
+
+        assert_eq!(bar_sections(&mut player, 5), ["A", "A", "B"]);
+    }
+
+    #[test]
+    fn program_goto_and_loop() {
+        let mut player = ProgramPlayer::new(Program::from_str(PROGRAM).unwrap());
+
+        player.next_bar();
+        player.set_loop(Some("b")).unwrap();
+        assert_eq!(bar_sections(&mut player, 3), ["B", "B", "B"]);
+
+        player.goto("A").unwrap();
+        player.set_loop(None).unwrap();
+        assert_eq!(bar_sections(&mut player, 5), ["A", "A", "B"]);
+        assert!(player.goto("C").is_err());
+    }
+}
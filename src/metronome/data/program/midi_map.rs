@@ -0,0 +1,285 @@
+use anyhow::anyhow;
+use midly::{MetaMessage, Smf, Timing, TrackEventKind};
+
+use super::{Program, Section};
+use crate::metronome::data::{
+    TEMPO_MAX, TEMPO_MIN, TempoType, TimeSignature, subdivision_setting::SubdivisionSetting,
+};
+
+/// 120 BPM, which a MIDI file plays at until its first tempo event
+const DEFAULT_MICROS_PER_QUARTER: u32 = 500_000;
+
+/// A tempo, time signature or marker event, at the tick it happens on
+enum MapEvent {
+    Tempo(u32),
+    TimeSignature(TimeSignature),
+    Marker(String),
+}
+
+/// The tempo in quarter notes per minute, which is what MIDI files count in
+fn tempo_of(micros_per_quarter: u32) -> anyhow::Result<i32> {
+    let tempo = (60_000_000.0 / micros_per_quarter as f64).round() as i32;
+
+    if !(TEMPO_MIN..=TEMPO_MAX).contains(&tempo) {
+        return Err(anyhow!(
+            "Tempo {} is outside of {}-{}!",
+            tempo,
+            TEMPO_MIN,
+            TEMPO_MAX
+        ));
+    }
+
+    Ok(tempo)
+}
+
+impl Program {
+    /// Turns the tempo map of a Standard MIDI File into sections, bar by bar. Each bar
+    /// uses the tempo and time signature in effect where it starts, bars with the same
+    /// settings are grouped together, and a marker at the start of a bar names the
+    /// section it starts. Tempos stay in quarter notes, like in the file. A time
+    /// signature that changes in the middle of a bar would move every later bar, so
+    /// it's an error
+    pub fn from_midi(bytes: &[u8]) -> anyhow::Result<Self> {
+        let smf = Smf::parse(bytes)?;
+
+        let Timing::Metrical(ticks_per_quarter) = smf.header.timing else {
+            return Err(anyhow!(
+                "MIDI files timed in SMPTE frames instead of beats aren't supported!"
+            ));
+        };
+        let ticks_per_quarter = ticks_per_quarter.as_int() as u64;
+        if ticks_per_quarter == 0 {
+            return Err(anyhow!("The MIDI file has 0 ticks per quarter note!"));
+        }
+
+        let mut map_events = Vec::new();
+        let mut end_tick = 0;
+
+        for track in &smf.tracks {
+            let mut tick = 0;
+
+            for track_event in track {
+                tick += track_event.delta.as_int() as u64;
+
+                let map_event = match track_event.kind {
+                    TrackEventKind::Meta(MetaMessage::Tempo(micros_per_quarter)) => {
+                        MapEvent::Tempo(micros_per_quarter.as_int().max(1))
+                    }
+                    TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, power, _, _)) => {
+                        let time_signature = format!("{}/{}", numerator, 1u32 << power.min(31));
+                        MapEvent::TimeSignature(time_signature.parse()?)
+                    }
+                    TrackEventKind::Meta(MetaMessage::Marker(text)) => {
+                        MapEvent::Marker(String::from_utf8_lossy(text).trim().to_string())
+                    }
+                    _ => continue,
+                };
+                map_events.push((tick, map_event));
+            }
+
+            end_tick = end_tick.max(tick);
+        }
+
+        map_events.sort_by_key(|(tick, _)| *tick);
+
+        let mut map_events = map_events.into_iter().peekable();
+        let mut sections: Vec<Section> = Vec::new();
+        let mut micros_per_quarter = DEFAULT_MICROS_PER_QUARTER;
+        let mut time_signature = TimeSignature::default();
+        let mut bar_start = 0;
+
+        loop {
+            let mut marker = None;
+
+            // Tempo changes and markers in the middle of a bar wait until the next one
+            while let Some((tick, map_event)) = map_events.next_if(|(tick, _)| *tick <= bar_start) {
+                match map_event {
+                    MapEvent::Tempo(micros) => micros_per_quarter = micros,
+                    MapEvent::TimeSignature(new_time_signature) if tick < bar_start => {
+                        return Err(anyhow!(
+                            "The time signature changes to {} in the middle of bar {}!",
+                            new_time_signature,
+                            sections.iter().map(|section| section.bars).sum::<u32>()
+                        ));
+                    }
+                    MapEvent::TimeSignature(new_time_signature) => {
+                        time_signature = new_time_signature
+                    }
+                    MapEvent::Marker(text) if !text.is_empty() => marker = Some(text),
+                    MapEvent::Marker(_) => {}
+                }
+            }
+
+            let tempo = tempo_of(micros_per_quarter)
+                .map_err(|err| anyhow!("Invalid tempo in bar {}: {}", sections.len() + 1, err))?;
+
+            match sections.last_mut() {
+                Some(section)
+                    if marker.is_none()
+                        && section.tempo == tempo
+                        && section.time_signature == time_signature =>
+                {
+                    section.bars += 1
+                }
+                _ => sections.push(Section {
+                    name: marker,
+                    bars: 1,
+                    tempo,
+                    time_signature,
+                    tempo_type: Some(TempoType::QuarterNote),
+                    subdivision: 1,
+                    subdivision_setting: SubdivisionSetting::default(),
+                    measure_numbers: Vec::new(),
+                }),
+            }
+
+            let bar_ticks =
+                ticks_per_quarter * 4 * time_signature.0 as u64 / time_signature.1 as u64;
+            if bar_ticks == 0 {
+                return Err(anyhow!(
+                    "Bar {} in {} is shorter than a tick of the MIDI file!",
+                    sections.iter().map(|section| section.bars).sum::<u32>(),
+                    time_signature
+                ));
+            }
+
+            bar_start += bar_ticks;
+            if bar_start >= end_tick {
+                break;
+            }
+        }
+
+        Ok(Self { sections })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metronome::data::{TempoType, TimeSignature, program::Program};
+    use midly::{
+        Format, Header, MetaMessage, Smf, Timing, TrackEvent, TrackEventKind,
+        num::{u15, u24, u28},
+    };
+
+    /// Writes a MIDI file with a single track of meta events, each with its delta
+    fn midi_file(ticks_per_quarter: u16, meta_events: &[(u32, MetaMessage)]) -> Vec<u8> {
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(ticks_per_quarter)),
+        ));
+        smf.tracks.push(
+            meta_events
+                .iter()
+                .map(|(delta, meta_message)| TrackEvent {
+                    delta: u28::new(*delta),
+                    kind: TrackEventKind::Meta(*meta_message),
+                })
+                .collect(),
+        );
+
+        let mut bytes = Vec::new();
+        smf.write_std(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// The name, bars, tempo, tempo type and time signature of a section
+    type SectionSummary<'a> = (Option<&'a str>, u32, i32, Option<TempoType>, TimeSignature);
+
+    #[test]
+    fn tempo_map_is_split_into_sections() {
+        let bytes = midi_file(
+            480,
+            &[
+                (0, MetaMessage::Tempo(u24::new(500_000))),
+                (0, MetaMessage::TimeSignature(4, 2, 24, 8)),
+                (0, MetaMessage::Marker(b"Intro")),
+                // A tempo change in the middle of bar 2 waits for bar 3
+                (2880, MetaMessage::Tempo(u24::new(600_000))),
+                (960, MetaMessage::TimeSignature(6, 3, 36, 8)),
+                (0, MetaMessage::Marker(b"Verse")),
+                (2880, MetaMessage::EndOfTrack),
+            ],
+        );
+        let program = Program::from_midi(&bytes).unwrap();
+
+        let sections: Vec<SectionSummary> = program
+            .sections
+            .iter()
+            .map(|section| {
+                (
+                    section.name.as_deref(),
+                    section.bars,
+                    section.tempo,
+                    section.tempo_type,
+                    section.time_signature,
+                )
+            })
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                (
+                    Some("Intro"),
+                    2,
+                    120,
+                    Some(TempoType::QuarterNote),
+                    TimeSignature(4, 4, None)
+                ),
+                // The tempo stays in quarter notes, even in 6/8
+                (
+                    Some("Verse"),
+                    2,
+                    100,
+                    Some(TempoType::QuarterNote),
+                    TimeSignature(6, 8, None)
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_midi_map() {
+        assert!(Program::from_midi(b"MThd").is_err());
+        assert!(
+            Program::from_midi(&midi_file(480, &[(0, MetaMessage::Tempo(u24::new(10)))])).is_err()
+        );
+    }
+
+    #[test]
+    fn time_signatures_in_the_middle_of_a_bar_are_rejected() {
+        let bytes = midi_file(
+            480,
+            &[
+                (2880, MetaMessage::TimeSignature(3, 2, 24, 8)),
+                (1440, MetaMessage::EndOfTrack),
+            ],
+        );
+
+        let error = Program::from_midi(&bytes).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The time signature changes to 3/4 in the middle of bar 2!"
+        );
+    }
+
+    #[test]
+    fn bars_without_ticks_are_rejected() {
+        let end_of_track = (1000, MetaMessage::EndOfTrack);
+        let error = Program::from_midi(&midi_file(0, &[end_of_track])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The MIDI file has 0 ticks per quarter note!"
+        );
+
+        // A bar of 1/128 is 3/4 of a tick at 24 ticks per quarter note
+        let error = Program::from_midi(&midi_file(
+            24,
+            &[(0, MetaMessage::TimeSignature(1, 7, 24, 8)), end_of_track],
+        ))
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Bar 1 in 1/128 is shorter than a tick of the MIDI file!"
+        );
+    }
+}
@@ -0,0 +1,395 @@
+use anyhow::{Context, anyhow};
+use roxmltree::{Document, Node};
+use std::{mem, str::FromStr};
+
+use super::{Program, Section};
+use crate::metronome::data::{
+    TempoType, TimeSignature, is_tempo_valid, subdivision_setting::SubdivisionSetting,
+};
+
+/// The tempo of a score until its first tempo mark, in quarter notes per minute
+const DEFAULT_QUARTER_TEMPO: f64 = 120.0;
+
+/// The text of the first child named `name`
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+/// The first number in a tempo mark, so marks like `ca. 120` or `120-132` still work
+fn first_number(s: &str) -> Option<f64> {
+    let start = s.find(|c: char| c.is_ascii_digit())?;
+    let number_str: String = s[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+
+    number_str.parse().ok()
+}
+
+fn tempo_type_of_beat_unit(beat_unit: &str, is_dotted: bool) -> Option<TempoType> {
+    use TempoType::*;
+
+    Some(match (beat_unit, is_dotted) {
+        ("quarter", false) => QuarterNote,
+        ("eighth", false) => EighthNote,
+        ("16th", false) => SixteenthNote,
+        ("half", false) => HalfNote,
+        ("whole", false) => WholeNote,
+        ("quarter", true) => DottedQuarter,
+        ("half", true) => DottedHalf,
+        ("whole", true) => DottedWhole,
+        _ => return None,
+    })
+}
+
+/// The time signature of a `<time>` element, like `3/4` or `2+2+3/8`
+fn time_signature_of(time: Node) -> anyhow::Result<Option<TimeSignature>> {
+    let (Some(beats), Some(beat_type)) = (child_text(time, "beats"), child_text(time, "beat-type"))
+    else {
+        // Unmeasured music, which keeps the time signature it had
+        return Ok(None);
+    };
+
+    TimeSignature::from_str(&format!("{}/{}", beats, beat_type)).map(Some)
+}
+
+/// The length of a note, `<backup>` or `<forward>`, in divisions of a quarter note
+fn duration_of(node: Node) -> i64 {
+    child_text(node, "duration")
+        .and_then(|duration| duration.parse().ok())
+        .unwrap_or(0)
+}
+
+/// A change of the settings somewhere in a measure
+enum ScoreChange {
+    TimeSignature(TimeSignature),
+    /// A `<sound>` tempo, in quarter notes per minute
+    QuarterTempo(f64),
+    /// A metronome mark, with its tempo in quarter notes per minute
+    Metronome(TempoType, f64),
+    Rehearsal(String),
+}
+
+/// The time signatures, tempo marks and rehearsal marks in a measure element
+fn changes_of(node: Node) -> anyhow::Result<Vec<ScoreChange>> {
+    let mut changes = Vec::new();
+
+    for node in node.descendants() {
+        match node.tag_name().name() {
+            "time" => {
+                if let Some(time_signature) = time_signature_of(node)? {
+                    changes.push(ScoreChange::TimeSignature(time_signature));
+                }
+            }
+            "sound" => {
+                if let Some(tempo) = node.attribute("tempo").and_then(first_number) {
+                    changes.push(ScoreChange::QuarterTempo(tempo));
+                }
+            }
+            "metronome" => {
+                let beat_unit = child_text(node, "beat-unit").unwrap_or_default();
+                let is_dotted = node
+                    .children()
+                    .any(|child| child.has_tag_name("beat-unit-dot"));
+
+                // Marks without a tempo, like metric modulations, are skipped
+                if let (Some(tempo_type), Some(tempo)) = (
+                    tempo_type_of_beat_unit(beat_unit, is_dotted),
+                    child_text(node, "per-minute").and_then(first_number),
+                ) {
+                    changes.push(ScoreChange::Metronome(
+                        tempo_type,
+                        tempo * tempo_type.to_note_length(),
+                    ));
+                }
+            }
+            "rehearsal" => {
+                if let Some(text) = node.text().map(str::trim).filter(|text| !text.is_empty()) {
+                    changes.push(ScoreChange::Rehearsal(text.to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(changes)
+}
+
+/// The settings a score has reached at some measure
+struct ScoreState {
+    time_signature: TimeSignature,
+    /// The unit of the last metronome mark, if there was one
+    tempo_type: Option<TempoType>,
+    quarter_tempo: f64,
+    /// Changes from the middle of the last measure, which wait until this one
+    pending_changes: Vec<ScoreChange>,
+}
+
+impl ScoreState {
+    /// Applies the time signature, tempo marks and rehearsal marks a measure starts
+    /// with, returning the rehearsal mark. Like in MIDI files, changes after the
+    /// downbeat wait until the next measure
+    fn read_measure(&mut self, measure: Node) -> anyhow::Result<Option<String>> {
+        let mut changes = mem::take(&mut self.pending_changes);
+        // How far into the measure the elements are, in divisions of a quarter note
+        let mut position: i64 = 0;
+
+        for node in measure.children() {
+            match node.tag_name().name() {
+                "note" if node.children().any(|child| child.has_tag_name("chord")) => {}
+                "note" | "forward" => position += duration_of(node),
+                "backup" => position -= duration_of(node),
+                _ => {
+                    let offset: i64 = child_text(node, "offset")
+                        .and_then(|offset| offset.parse().ok())
+                        .unwrap_or(0);
+                    let node_changes = changes_of(node)?;
+
+                    if position + offset > 0 {
+                        self.pending_changes.extend(node_changes);
+                    } else {
+                        changes.extend(node_changes);
+                    }
+                }
+            }
+        }
+
+        let mut rehearsal_mark = None;
+        for change in changes {
+            match change {
+                ScoreChange::TimeSignature(time_signature) => self.time_signature = time_signature,
+                ScoreChange::QuarterTempo(quarter_tempo) => self.quarter_tempo = quarter_tempo,
+                ScoreChange::Metronome(tempo_type, quarter_tempo) => {
+                    self.tempo_type = Some(tempo_type);
+                    self.quarter_tempo = quarter_tempo;
+                }
+                ScoreChange::Rehearsal(text) => rehearsal_mark = Some(text),
+            }
+        }
+
+        Ok(rehearsal_mark)
+    }
+
+    /// The tempo in the unit of the last metronome mark, or the unit the metronome
+    /// counts in for the time signature if there's none
+    fn tempo(&self) -> (i32, Option<TempoType>) {
+        let tempo_type = self
+            .tempo_type
+            .unwrap_or(TempoType::get_default_based(self.time_signature));
+        let tempo = (self.quarter_tempo / tempo_type.to_note_length()).round() as i32;
+
+        (tempo, self.tempo_type)
+    }
+}
+
+impl Program {
+    /// Turns the measures of the first part of a MusicXML score into sections, with
+    /// the time signature and tempo each measure starts with. Measures with the same
+    /// settings are grouped together, rehearsal marks name the sections, and bars are
+    /// shown with the score's measure numbers. A pickup measure is skipped. Repeats
+    /// aren't followed
+    pub fn from_music_xml(xml: &str) -> anyhow::Result<Self> {
+        let document = Document::parse(xml)?;
+        let score = document.root_element();
+
+        if !score.has_tag_name("score-partwise") {
+            return Err(anyhow!(
+                "Only partwise MusicXML scores are supported, not `{}`!",
+                score.tag_name().name()
+            ));
+        }
+
+        let part = score
+            .children()
+            .find(|node| node.has_tag_name("part"))
+            .ok_or_else(|| anyhow!("The score has no parts!"))?;
+
+        let mut score_state = ScoreState {
+            time_signature: TimeSignature::default(),
+            tempo_type: None,
+            quarter_tempo: DEFAULT_QUARTER_TEMPO,
+            pending_changes: Vec::new(),
+        };
+        let mut sections: Vec<Section> = Vec::new();
+
+        for measure in part.children().filter(|node| node.has_tag_name("measure")) {
+            let measure_number = measure.attribute("number").unwrap_or("?");
+            let rehearsal_mark = score_state
+                .read_measure(measure)
+                .with_context(|| format!("Invalid measure {}", measure_number))?;
+
+            if sections.is_empty() && measure.attribute("implicit") == Some("yes") {
+                continue;
+            }
+
+            let (tempo, tempo_type) = score_state.tempo();
+            if !is_tempo_valid(tempo) {
+                return Err(anyhow!(
+                    "Invalid tempo `{}` in measure {}!",
+                    tempo,
+                    measure_number
+                ));
+            }
+
+            match sections.last_mut() {
+                Some(section)
+                    if rehearsal_mark.is_none()
+                        && section.tempo == tempo
+                        && section.tempo_type == tempo_type
+                        && section.time_signature == score_state.time_signature =>
+                {
+                    section.bars += 1;
+                    section.measure_numbers.push(measure_number.to_string());
+                }
+                _ => sections.push(Section {
+                    name: rehearsal_mark,
+                    bars: 1,
+                    tempo,
+                    time_signature: score_state.time_signature,
+                    tempo_type,
+                    subdivision: 1,
+                    subdivision_setting: SubdivisionSetting::default(),
+                    measure_numbers: vec![measure_number.to_string()],
+                }),
+            }
+        }
+
+        if sections.is_empty() {
+            return Err(anyhow!("The score has no measures!"));
+        }
+
+        Ok(Self { sections })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metronome::data::{
+        TempoType, TimeSignature,
+        program::{Program, ProgramPlayer},
+    };
+
+    const SCORE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <score-partwise version="4.0">
+          <part-list><score-part id="P1"><part-name>Violin</part-name></score-part></part-list>
+          <part id="P1">
+            <measure number="0" implicit="yes">
+              <attributes><time><beats>4</beats><beat-type>4</beat-type></time></attributes>
+              <sound tempo="90"/>
+            </measure>
+            <measure number="1"/>
+            <measure number="2">
+              <direction>
+                <direction-type><rehearsal>A</rehearsal></direction-type>
+              </direction>
+              <attributes><time><beats>2+2+3</beats><beat-type>8</beat-type></time></attributes>
+              <direction>
+                <direction-type>
+                  <metronome><beat-unit>eighth</beat-unit><per-minute>ca. 240</per-minute></metronome>
+                </direction-type>
+              </direction>
+            </measure>
+            <measure number="3"/>
+            <measure number="4">
+              <attributes><time><beats>6</beats><beat-type>8</beat-type></time></attributes>
+              <direction>
+                <direction-type>
+                  <metronome><beat-unit>quarter</beat-unit><beat-unit-dot/><per-minute>60</per-minute></metronome>
+                </direction-type>
+                <sound tempo="90"/>
+              </direction>
+            </measure>
+          </part>
+        </score-partwise>"#;
+
+    /// The name, bars, tempo, tempo type and time signature of a section
+    type SectionSummary<'a> = (Option<&'a str>, u32, i32, Option<TempoType>, String);
+
+    #[test]
+    fn measures_are_split_into_sections() {
+        let program = Program::from_music_xml(SCORE).unwrap();
+
+        let sections: Vec<SectionSummary> = program
+            .sections
+            .iter()
+            .map(|section| {
+                (
+                    section.name.as_deref(),
+                    section.bars,
+                    section.tempo,
+                    section.tempo_type,
+                    section.time_signature.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                (None, 1, 90, None, "4/4".to_string()),
+                (
+                    Some("A"),
+                    2,
+                    240,
+                    Some(TempoType::EighthNote),
+                    "2+2+3/8".parse::<TimeSignature>().unwrap().to_string()
+                ),
+                (
+                    None,
+                    1,
+                    60,
+                    Some(TempoType::DottedQuarter),
+                    "6/8".to_string()
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn changes_after_the_downbeat_wait_for_the_next_measure() {
+        let score = r#"<score-partwise><part>
+            <measure number="1">
+              <attributes><divisions>1</divisions></attributes>
+              <note><duration>2</duration></note>
+              <note><chord/><duration>2</duration></note>
+              <direction><direction-type><words>rit.</words></direction-type><sound tempo="60"/></direction>
+              <note><duration>2</duration></note>
+            </measure>
+            <measure number="2"><note><duration>4</duration></note></measure>
+            <measure number="2a"><note><duration>4</duration></note></measure>
+        </part></score-partwise>"#;
+        let program = Program::from_music_xml(score).unwrap();
+
+        let sections: Vec<(i32, &[String])> = program
+            .sections
+            .iter()
+            .map(|section| (section.tempo, section.measure_numbers.as_slice()))
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                (120, ["1".to_string()].as_slice()),
+                (60, ["2".to_string(), "2a".to_string()].as_slice())
+            ]
+        );
+
+        // The score's own measure numbers are shown
+        let mut player = ProgramPlayer::new(program);
+        for _ in 0..3 {
+            player.next_bar();
+        }
+        assert!(player.to_string().ends_with("Bar 2a"));
+    }
+
+    #[test]
+    fn invalid_score() {
+        assert!(Program::from_music_xml("<score-timewise/>").is_err());
+        assert!(Program::from_music_xml("<score-partwise><part/></score-partwise>").is_err());
+        assert!(Program::from_music_xml(
+            r#"<score-partwise><part><measure number="1"><sound tempo="1000"/></measure></part></score-partwise>"#
+        )
+        .is_err());
+    }
+}
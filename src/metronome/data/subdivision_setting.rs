@@ -1,12 +1,12 @@
 use anyhow::anyhow;
 use std::{fmt, str::FromStr};
 
-use super::beat::BeatInfo;
+use super::{beat::BeatInfo, beat_grouping::BeatGrouping};
 
 const PLAY_SUBDIVISION_CHAR: char = 'x';
 const SILENCE_SUBDIVISION_CHAR: char = '-';
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct SubdivisionSetting {
     pub play_beat: Vec<bool>,
 }
@@ -55,16 +55,18 @@ impl SubdivisionSetting {
     pub fn should_play_subdivision_beat(
         &self,
         beat_info: BeatInfo,
-        time_signature_is_eighths: bool,
+        beat_grouping: Option<BeatGrouping>,
         has_subdivision: bool,
     ) -> bool {
         if beat_info == (0, 0) {
             return true;
         }
-        let play_beat = if time_signature_is_eighths && !has_subdivision {
-            self.play_beat.get(beat_info.current_beat as usize % 3)
-        } else {
-            self.play_beat.get(beat_info.subdivided_beat as usize)
+        let play_beat = match beat_grouping {
+            // Without a subdivision, the beats in a group act as its subdivided beats
+            Some(beat_grouping) if !has_subdivision => self
+                .play_beat
+                .get(beat_grouping.position_in_group(beat_info.current_beat) as usize),
+            _ => self.play_beat.get(beat_info.subdivided_beat as usize),
         };
 
         play_beat.copied().unwrap_or(true)
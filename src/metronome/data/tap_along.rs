@@ -0,0 +1,167 @@
+use std::fmt;
+
+/// How far the average tap of a bar can be from the beat, in milliseconds, before the
+/// bar counts as rushing or dragging
+const STEADY_TOLERANCE_MS: f64 = 15.0;
+
+/// How many of the latest offsets are shown
+pub const TAP_ALONG_SHOWN_OFFSETS: usize = 6;
+
+/// Whether the taps of a bar were ahead of, behind or with the click
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingVerdict {
+    Rushing,
+    Dragging,
+    Steady,
+}
+
+impl TimingVerdict {
+    fn from_mean_offset(mean_offset_ms: f64) -> Self {
+        if mean_offset_ms < -STEADY_TOLERANCE_MS {
+            Self::Rushing
+        } else if mean_offset_ms > STEADY_TOLERANCE_MS {
+            Self::Dragging
+        } else {
+            Self::Steady
+        }
+    }
+}
+
+impl fmt::Display for TimingVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let string = match self {
+            Self::Rushing => "rushing",
+            Self::Dragging => "dragging",
+            Self::Steady => "steady",
+        };
+
+        write!(f, "{}", string)
+    }
+}
+
+fn mean(offsets_ms: &[f64]) -> f64 {
+    offsets_ms.iter().sum::<f64>() / offsets_ms.len() as f64
+}
+
+fn std_dev(offsets_ms: &[f64]) -> f64 {
+    let mean = mean(offsets_ms);
+    let variance = offsets_ms
+        .iter()
+        .map(|offset_ms| (offset_ms - mean).powi(2))
+        .sum::<f64>()
+        / offsets_ms.len() as f64;
+
+    variance.sqrt()
+}
+
+/// Practice mode where taps along with the click are compared with the nearest
+/// subdivided beat. Offsets are in milliseconds, negative when early and positive
+/// when late
+#[derive(Debug, Clone, Default)]
+pub struct TapAlong {
+    offsets_ms: Vec<f64>,
+    bar_offsets_ms: Vec<f64>,
+    bar_verdicts: Vec<TimingVerdict>,
+}
+
+impl TapAlong {
+    pub fn tap(&mut self, offset_ms: f64) {
+        self.offsets_ms.push(offset_ms);
+        self.bar_offsets_ms.push(offset_ms);
+    }
+
+    /// Gives a verdict on the bar that just ended, if anything was tapped in it
+    pub fn next_bar(&mut self) {
+        if self.bar_offsets_ms.is_empty() {
+            return;
+        }
+
+        self.bar_verdicts
+            .push(TimingVerdict::from_mean_offset(mean(&self.bar_offsets_ms)));
+        self.bar_offsets_ms.clear();
+    }
+
+    pub fn latest_offsets_ms(&self) -> &[f64] {
+        let start = self
+            .offsets_ms
+            .len()
+            .saturating_sub(TAP_ALONG_SHOWN_OFFSETS);
+        &self.offsets_ms[start..]
+    }
+
+    pub fn last_bar_verdict(&self) -> Option<TimingVerdict> {
+        self.bar_verdicts.last().copied()
+    }
+
+    pub fn num_tapped(&self) -> usize {
+        self.offsets_ms.len()
+    }
+
+    /// The mean offset of every tap, and the standard deviation around it
+    pub fn mean_and_std_dev(&self) -> Option<(f64, f64)> {
+        (!self.offsets_ms.is_empty()).then(|| (mean(&self.offsets_ms), std_dev(&self.offsets_ms)))
+    }
+
+    fn num_bars(&self, verdict: TimingVerdict) -> usize {
+        self.bar_verdicts
+            .iter()
+            .filter(|bar_verdict| **bar_verdict == verdict)
+            .count()
+    }
+
+    /// What's printed once the mode is left
+    pub fn summary(&self) -> String {
+        let Some((mean, std_dev)) = self.mean_and_std_dev() else {
+            return "Tap-along finished without any taps.".to_string();
+        };
+
+        format!(
+            "Tap-along: {} taps, {:+.1} ms ± {:.1} ms on average ({}). Bars: {} steady, {} rushing, {} dragging.",
+            self.num_tapped(),
+            mean,
+            std_dev,
+            TimingVerdict::from_mean_offset(mean),
+            self.num_bars(TimingVerdict::Steady),
+            self.num_bars(TimingVerdict::Rushing),
+            self.num_bars(TimingVerdict::Dragging)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metronome::data::tap_along::{TapAlong, TimingVerdict};
+
+    #[test]
+    fn bars_get_verdicts() {
+        let mut tap_along = TapAlong::default();
+
+        for offset_ms in [-30.0, -20.0, -25.0, -5.0] {
+            tap_along.tap(offset_ms);
+        }
+        tap_along.next_bar();
+        assert_eq!(tap_along.last_bar_verdict(), Some(TimingVerdict::Rushing));
+
+        // A bar without taps doesn't get a verdict
+        tap_along.next_bar();
+        assert_eq!(tap_along.last_bar_verdict(), Some(TimingVerdict::Rushing));
+
+        for offset_ms in [5.0, -5.0, 10.0, -10.0] {
+            tap_along.tap(offset_ms);
+        }
+        tap_along.next_bar();
+        assert_eq!(tap_along.last_bar_verdict(), Some(TimingVerdict::Steady));
+
+        let (mean, std_dev) = tap_along.mean_and_std_dev().unwrap();
+        assert_eq!(mean, -10.0);
+        assert!((std_dev - 13.229).abs() < 0.001);
+        assert_eq!(
+            tap_along.latest_offsets_ms(),
+            [-25.0, -5.0, 5.0, -5.0, 10.0, -10.0]
+        );
+        assert_eq!(
+            tap_along.summary(),
+            "Tap-along: 8 taps, -10.0 ms ± 13.2 ms on average (steady). Bars: 1 steady, 1 rushing, 0 dragging."
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::anyhow;
+use std::{fmt, str::FromStr};
+
+use super::is_tempo_valid;
+
+/// What a tempo ramp does once it reaches its target tempo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RampEnd {
+    Hold,
+    Restart,
+}
+
+/// A speed trainer that changes the tempo by `step` every `bars_per_step` bars, from
+/// `start_tempo` until `target_tempo`
+#[derive(Debug, Clone)]
+pub struct TempoRamp {
+    start_tempo: i32,
+    target_tempo: i32,
+    step: i32,
+    bars_per_step: u32,
+    ramp_end: RampEnd,
+    bars_started: u32,
+}
+
+impl TempoRamp {
+    pub fn start_tempo(&self) -> i32 {
+        self.start_tempo
+    }
+
+    /// The number of steps it takes to get from the start tempo to the target tempo
+    pub fn num_steps(&self) -> u32 {
+        (self.target_tempo - self.start_tempo)
+            .unsigned_abs()
+            .div_ceil(self.step as u32)
+    }
+
+    /// The step the ramp is currently on, with the start tempo being step 0
+    pub fn current_step(&self) -> u32 {
+        let step = self.bars_started.saturating_sub(1) / self.bars_per_step;
+
+        match self.ramp_end {
+            RampEnd::Hold => step.min(self.num_steps()),
+            RampEnd::Restart => step % (self.num_steps() + 1),
+        }
+    }
+
+    pub fn current_tempo(&self) -> i32 {
+        let change = (self.step * self.current_step() as i32)
+            .min((self.target_tempo - self.start_tempo).abs());
+
+        if self.target_tempo >= self.start_tempo {
+            self.start_tempo + change
+        } else {
+            self.start_tempo - change
+        }
+    }
+
+    /// Moves the ramp to the next bar, returning the tempo for that bar
+    pub fn next_bar(&mut self) -> i32 {
+        self.bars_started += 1;
+        self.current_tempo()
+    }
+}
+
+impl FromStr for TempoRamp {
+    type Err = anyhow::Error;
+
+    /// Parses a ramp with the format of `<START> <TARGET> <STEP> <BARS> [hold | restart]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let args: Vec<&str> = s.split_whitespace().collect();
+
+        if !matches!(args.len(), 4 | 5) {
+            return Err(anyhow!(
+                "Invalid ramp! Example ramp: `80 140 4 2` goes from 80 to 140, adding 4 every 2 bars"
+            ));
+        }
+
+        let start_tempo: i32 = args[0].parse()?;
+        let target_tempo: i32 = args[1].parse()?;
+        let step: i32 = args[2].parse()?;
+        let bars_per_step: u32 = args[3].parse()?;
+
+        if !is_tempo_valid(start_tempo) || !is_tempo_valid(target_tempo) {
+            return Err(anyhow!("Ramp tempos must be valid tempos!"));
+        }
+        if step < 1 || bars_per_step < 1 {
+            return Err(anyhow!("Ramp step and bars must be at least 1!"));
+        }
+
+        let ramp_end = match args.get(4).copied().unwrap_or("hold") {
+            "hold" => RampEnd::Hold,
+            "restart" => RampEnd::Restart,
+            other => {
+                return Err(anyhow!(
+                    "Ramp can only `hold` or `restart`, not `{}`!",
+                    other
+                ));
+            }
+        };
+
+        Ok(Self {
+            start_tempo,
+            target_tempo,
+            step,
+            bars_per_step,
+            ramp_end,
+            bars_started: 0,
+        })
+    }
+}
+
+impl fmt::Display for TempoRamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} (step {}/{}, {}{} every {} bar{}, then {})",
+            self.start_tempo,
+            self.target_tempo,
+            self.current_step(),
+            self.num_steps(),
+            if self.target_tempo >= self.start_tempo {
+                '+'
+            } else {
+                '-'
+            },
+            self.step,
+            self.bars_per_step,
+            if self.bars_per_step == 1 { "" } else { "s" },
+            match self.ramp_end {
+                RampEnd::Hold => "hold",
+                RampEnd::Restart => "restart",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metronome::data::tempo_ramp::TempoRamp;
+    use std::str::FromStr;
+
+    fn tempos(ramp: &mut TempoRamp, bars: usize) -> Vec<i32> {
+        (0..bars).map(|_| ramp.next_bar()).collect()
+    }
+
+    #[test]
+    fn ramp_holds_at_target() {
+        let mut ramp = TempoRamp::from_str("80 90 4 2").unwrap();
+
+        assert_eq!(ramp.num_steps(), 3);
+        assert_eq!(tempos(&mut ramp, 9), [80, 80, 84, 84, 88, 88, 90, 90, 90]);
+    }
+
+    #[test]
+    fn ramp_restarts_after_target() {
+        let mut ramp = TempoRamp::from_str("100 90 5 1 restart").unwrap();
+
+        assert_eq!(tempos(&mut ramp, 7), [100, 95, 90, 100, 95, 90, 100]);
+    }
+
+    #[test]
+    fn invalid_ramp() {
+        assert!(TempoRamp::from_str("80 140 4").is_err());
+        assert!(TempoRamp::from_str("80 140 0 2").is_err());
+        assert!(TempoRamp::from_str("80 1000 4 2").is_err());
+        assert!(TempoRamp::from_str("80 140 4 2 loop").is_err());
+    }
+}
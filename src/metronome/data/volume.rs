@@ -0,0 +1,135 @@
+use anyhow::anyhow;
+use std::{fmt, ops::RangeInclusive};
+
+use super::beat::accent::MetronomeBeatAccent;
+
+pub const VOLUME_MAX: u8 = 100;
+pub const VOLUME_RANGE: RangeInclusive<i64> = 0..=(VOLUME_MAX as i64);
+
+/// The master volume and the volume of each accent, all in percent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Volume {
+    pub master: u8,
+    pub accented: u8,
+    pub beat: u8,
+    pub subdivision: u8,
+    pub polyrhythm: u8,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self {
+            master: VOLUME_MAX,
+            accented: VOLUME_MAX,
+            beat: VOLUME_MAX,
+            subdivision: VOLUME_MAX,
+            polyrhythm: VOLUME_MAX,
+        }
+    }
+}
+
+fn percent_to_gain(percent: u8) -> f32 {
+    percent as f32 / VOLUME_MAX as f32
+}
+
+impl Volume {
+    fn accent_volume_mut(&mut self, accent: MetronomeBeatAccent) -> &mut u8 {
+        match accent {
+            MetronomeBeatAccent::Accented => &mut self.accented,
+            MetronomeBeatAccent::Beat => &mut self.beat,
+            MetronomeBeatAccent::Subdivision => &mut self.subdivision,
+            MetronomeBeatAccent::Polyrhythm => &mut self.polyrhythm,
+        }
+    }
+
+    pub fn master_gain(&self) -> f32 {
+        percent_to_gain(self.master)
+    }
+
+    /// How loud a click with `accent` is played, with 1 being unchanged
+    pub fn gain(&self, accent: MetronomeBeatAccent) -> f32 {
+        // A copy is cheap, and reading it through the same accessor keeps one match
+        let mut volume = *self;
+
+        self.master_gain() * percent_to_gain(*volume.accent_volume_mut(accent))
+    }
+
+    /// Sets the volume from the arguments of the `volume` command, which are either
+    /// `<PERCENT>` for the master volume or `<ACCENT> <PERCENT>`
+    pub fn set_from_str(&mut self, s: &str) -> anyhow::Result<()> {
+        let args: Vec<&str> = s.split_whitespace().collect();
+
+        let (volume, percent_str) = match args.as_slice() {
+            [percent_str] => (&mut self.master, *percent_str),
+            [accent_str, percent_str] => {
+                let accent = match *accent_str {
+                    "accented" => MetronomeBeatAccent::Accented,
+                    "beat" => MetronomeBeatAccent::Beat,
+                    "subdivision" => MetronomeBeatAccent::Subdivision,
+                    "poly" => MetronomeBeatAccent::Polyrhythm,
+                    _ => {
+                        return Err(anyhow!(
+                            "Invalid accent `{}`! Must be accented, beat, subdivision or poly",
+                            accent_str
+                        ));
+                    }
+                };
+
+                (self.accent_volume_mut(accent), *percent_str)
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Example volume: `volume 80` or `volume subdivision 40`"
+                ));
+            }
+        };
+
+        match percent_str.trim_end_matches('%').parse::<u8>() {
+            Ok(percent) if percent <= VOLUME_MAX => {
+                *volume = percent;
+                Ok(())
+            }
+            _ => Err(anyhow!(
+                "Volume must be a whole number between 0-{}!",
+                VOLUME_MAX
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Volume {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}% ({} {}% {} {}% {} {}%)",
+            self.master,
+            MetronomeBeatAccent::Accented.to_char(),
+            self.accented,
+            MetronomeBeatAccent::Beat.to_char(),
+            self.beat,
+            MetronomeBeatAccent::Subdivision.to_char(),
+            self.subdivision
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metronome::data::{beat::accent::MetronomeBeatAccent, volume::Volume};
+
+    #[test]
+    fn volume_from_command() {
+        let mut volume = Volume::default();
+
+        volume.set_from_str("50").unwrap();
+        volume.set_from_str("subdivision 40%").unwrap();
+
+        assert_eq!(volume.gain(MetronomeBeatAccent::Accented), 0.5);
+        assert_eq!(volume.gain(MetronomeBeatAccent::Subdivision), 0.2);
+        assert_eq!(volume.to_string(), "50% (X 100% x 100% . 40%)");
+
+        assert!(volume.set_from_str("101").is_err());
+        assert!(volume.set_from_str("downbeat 50").is_err());
+        assert!(volume.set_from_str("").is_err());
+    }
+}
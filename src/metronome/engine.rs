@@ -0,0 +1,630 @@
+use std::{
+    sync::{
+        Arc, RwLock,
+        mpsc::{self, RecvTimeoutError},
+    },
+    time::Instant,
+};
+
+use crate::{
+    clock::SharedClock,
+    timer::{play_timer_alarm, render_tracker::TimerRenderTracker},
+    user_input::UserInput,
+};
+
+use super::{
+    data::{
+        MetronomeData,
+        beat::{BeatInfo, MetronomeBeatTracker, accent::MetronomeBeatAccent},
+    },
+    sound::{AudioEngine, SoundSlot, SoundSource},
+};
+
+/// What a [`Metronome`] tells its listeners about, as it happens
+#[derive(Debug, Clone)]
+pub enum MetronomeEvent {
+    /// A subdivided beat is scheduled to be heard at `timestamp`, with the accent of
+    /// its click, or `None` if the click is silenced
+    Beat {
+        timestamp: Instant,
+        beat_info: BeatInfo,
+        click: Option<MetronomeBeatAccent>,
+    },
+    /// A pulse of the polyrhythm layer is scheduled for `timestamp`
+    PolyrhythmPulse { timestamp: Instant, is_muted: bool },
+    /// Another second of the timer went by
+    TimerTick,
+    /// A command was carried out
+    Command(UserInput),
+    /// A command couldn't be carried out, like a tempo that's out of range
+    CommandFailed {
+        user_input: UserInput,
+        error: String,
+    },
+    /// Something to tell the user, like how well they tapped along
+    Message(String),
+}
+
+type Listener = Box<dyn FnMut(&MetronomeEvent) -> anyhow::Result<()>>;
+
+/// The beat engine. It keeps the beats on time, plays the clicks, and carries out
+/// the commands sent through [`Metronome::sender`], telling every listener added with
+/// [`Metronome::on_event`] about each beat along the way.
+///
+/// The settings live in a shared [`MetronomeData`], which listeners can read to show
+/// them. Commands should be sent instead of changing it directly, so the beats stay
+/// on the grid.
+pub struct Metronome {
+    metronome_data: Arc<RwLock<MetronomeData>>,
+    clock: SharedClock,
+    beat_tracker: MetronomeBeatTracker,
+    timer_render_tracker: TimerRenderTracker,
+    /// `None` for a silent engine, which only sends events
+    audio_engine: Option<AudioEngine>,
+    timer_alarm_sink: Option<rodio::Sink>,
+    /// Handed out to whoever sends commands, and dropped once the engine runs so it
+    /// stops when they're all gone
+    sender: Option<mpsc::Sender<UserInput>>,
+    receiver: mpsc::Receiver<UserInput>,
+    listeners: Vec<Listener>,
+}
+
+impl Metronome {
+    /// An engine that plays the clicks on the default audio output
+    pub fn new(metronome_data: MetronomeData) -> anyhow::Result<Self> {
+        let mut metronome = Self::silent(metronome_data);
+        metronome.audio_engine = Some(AudioEngine::new()?);
+
+        Ok(metronome)
+    }
+
+    /// An engine that keeps time and sends events without playing anything
+    pub fn silent(metronome_data: MetronomeData) -> Self {
+        let clock = metronome_data.clock();
+        let metronome_data = Arc::new(RwLock::new(metronome_data));
+        let (sender, receiver) = mpsc::channel();
+
+        Self {
+            beat_tracker: MetronomeBeatTracker::new(
+                Arc::clone(&metronome_data),
+                SharedClock::clone(&clock),
+            ),
+            timer_render_tracker: TimerRenderTracker::new(
+                Arc::clone(&metronome_data),
+                SharedClock::clone(&clock),
+            ),
+            clock,
+            metronome_data,
+            audio_engine: None,
+            timer_alarm_sink: None,
+            sender: Some(sender),
+            receiver,
+            listeners: Vec::new(),
+        }
+    }
+
+    /// The settings, shared with the engine
+    pub fn data(&self) -> Arc<RwLock<MetronomeData>> {
+        Arc::clone(&self.metronome_data)
+    }
+
+    /// Where to send commands. The engine keeps running until every sender is dropped
+    pub fn sender(&self) -> mpsc::Sender<UserInput> {
+        self.sender
+            .clone()
+            .expect("senders are handed out before the engine runs")
+    }
+
+    /// Calls `listener` for every event. An error from a listener stops the engine
+    pub fn on_event(
+        &mut self,
+        listener: impl FnMut(&MetronomeEvent) -> anyhow::Result<()> + 'static,
+    ) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Replaces a sound, or puts back the built-in one if `sound_source` is `None`. A
+    /// silent engine ignores sounds
+    pub fn set_sound(
+        &mut self,
+        sound_slot: SoundSlot,
+        sound_source: Option<&SoundSource>,
+    ) -> anyhow::Result<()> {
+        match self.audio_engine {
+            Some(ref mut audio_engine) => audio_engine.set_sound(sound_slot, sound_source),
+            None => Ok(()),
+        }
+    }
+
+    fn send_event(&mut self, event: MetronomeEvent) -> anyhow::Result<()> {
+        for listener in &mut self.listeners {
+            listener(&event)?;
+        }
+
+        Ok(())
+    }
+
+    fn play_beat(&mut self, timestamp: Instant) -> anyhow::Result<()> {
+        self.beat_tracker.move_to_next_subdivided_beat();
+
+        let (beat_info, click) = {
+            let d = self.metronome_data.read().unwrap();
+            let click = d
+                .current_click()
+                .map(|accent| (accent, d.click_pan(accent), d.volume.gain(accent)));
+
+            (d.beat_info, click)
+        };
+
+        if let Some(ref mut audio_engine) = self.audio_engine
+            && let Some((accent, pan, gain)) = click
+        {
+            audio_engine.schedule_click(timestamp, accent, pan, gain);
+        }
+
+        self.send_event(MetronomeEvent::Beat {
+            timestamp,
+            beat_info,
+            click: click.map(|(accent, _, _)| accent),
+        })
+    }
+
+    fn play_polyrhythm_pulse(&mut self, timestamp: Instant) -> anyhow::Result<()> {
+        self.beat_tracker.move_to_next_polyrhythm_pulse();
+
+        let accent = MetronomeBeatAccent::Polyrhythm;
+        let (is_muted, pan, gain) = {
+            let d = self.metronome_data.read().unwrap();
+            (d.is_muted(), d.click_pan(accent), d.volume.gain(accent))
+        };
+
+        if let Some(ref mut audio_engine) = self.audio_engine
+            && !is_muted
+        {
+            audio_engine.schedule_click(timestamp, accent, pan, gain);
+        }
+
+        self.send_event(MetronomeEvent::PolyrhythmPulse {
+            timestamp,
+            is_muted,
+        })
+    }
+
+    fn tick_timer(&mut self) -> anyhow::Result<()> {
+        self.timer_render_tracker.just_rendered();
+
+        let is_finished = {
+            let d = self.metronome_data.read().unwrap();
+            d.timer
+                .as_ref()
+                .is_some_and(|timer| timer.time_remaining().is_zero())
+        };
+
+        if is_finished && let Some(ref audio_engine) = self.audio_engine {
+            self.timer_alarm_sink = Some(play_timer_alarm(
+                audio_engine.mixer(),
+                audio_engine.custom_alarm(),
+                self.metronome_data.read().unwrap().volume.master_gain(),
+            )?);
+        }
+
+        self.send_event(MetronomeEvent::TimerTick)
+    }
+
+    /// Carries out a command right away, like one sent through [`Metronome::sender`].
+    /// A command that fails is sent to the listeners instead of stopping the engine
+    pub fn execute(&mut self, user_input: UserInput) -> anyhow::Result<()> {
+        match self.carry_out(&user_input) {
+            Ok(Some(message)) => self.send_event(MetronomeEvent::Message(message))?,
+            Ok(None) => {}
+            Err(err) => {
+                return self.send_event(MetronomeEvent::CommandFailed {
+                    user_input,
+                    error: format!("{:#}", err),
+                });
+            }
+        }
+
+        self.send_event(MetronomeEvent::Command(user_input))
+    }
+
+    fn carry_out(&mut self, user_input: &UserInput) -> anyhow::Result<Option<String>> {
+        let message = self.metronome_data.write().unwrap().execute(user_input)?;
+
+        match user_input {
+            UserInput::Pause | UserInput::Resume | UserInput::TogglePause | UserInput::StopTap => {
+                self.beat_tracker.restart()
+            }
+            UserInput::StopTimer | UserInput::SetTimer(_) => self.timer_alarm_sink = None,
+            // Taps along with the click are compared with the beat grid
+            UserInput::TapBeat(timestamp) => {
+                let offset_ms = self.beat_tracker.offset_from_nearest_beat(*timestamp);
+                if let Some(ref mut tap_along) = self.metronome_data.write().unwrap().tap_along {
+                    tap_along.tap(offset_ms);
+                }
+            }
+            UserInput::SetVolume(_) => {
+                if let Some(ref timer_alarm_sink) = self.timer_alarm_sink {
+                    timer_alarm_sink
+                        .set_volume(self.metronome_data.read().unwrap().volume.master_gain());
+                }
+            }
+            UserInput::SetSound(sound_slot_str, sound_source_str) => {
+                let sound_slot = sound_slot_str.parse::<SoundSlot>()?;
+                let sound_source = match sound_source_str.as_str() {
+                    "" | "default" => None,
+                    _ => Some(sound_source_str.parse::<SoundSource>()?),
+                };
+
+                self.set_sound(sound_slot, sound_source.as_ref())?;
+            }
+            _ => {}
+        }
+
+        Ok(message)
+    }
+
+    /// The next beat and polyrhythm pulse, or `None` for either if it isn't coming
+    fn next_timestamps(&mut self) -> (Option<Instant>, Option<Instant>) {
+        if self.metronome_data.read().unwrap().is_paused {
+            return (None, None);
+        }
+
+        (
+            Some(self.beat_tracker.next_beat_timestamp()),
+            self.beat_tracker.next_polyrhythm_timestamp(),
+        )
+    }
+
+    /// When the next beat, polyrhythm pulse or timer tick is due, or `None` if nothing
+    /// is coming until a command changes that
+    pub fn next_wake_up(&mut self) -> Option<Instant> {
+        let (next_beat_timestamp, next_polyrhythm_timestamp) = self.next_timestamps();
+
+        next_beat_timestamp
+            .into_iter()
+            .chain(next_polyrhythm_timestamp)
+            .chain(self.timer_render_tracker.next_render_timestamp())
+            .min()
+    }
+
+    /// Plays every beat, polyrhythm pulse and timer tick that's due by now on the clock
+    pub fn play_due(&mut self) -> anyhow::Result<()> {
+        loop {
+            let now = self.clock.now();
+            let (next_beat_timestamp, next_polyrhythm_timestamp) = self.next_timestamps();
+
+            if let Some(beat_timestamp) = next_beat_timestamp
+                && beat_timestamp <= now
+            {
+                self.play_beat(beat_timestamp)?;
+
+                // There might be another beat due already, so check again
+                continue;
+            }
+
+            if let Some(pulse_timestamp) = next_polyrhythm_timestamp
+                && pulse_timestamp <= now
+            {
+                self.play_polyrhythm_pulse(pulse_timestamp)?;
+                continue;
+            }
+
+            break;
+        }
+
+        if self.timer_render_tracker.should_render_timer() {
+            self.tick_timer()?;
+        }
+
+        Ok(())
+    }
+
+    /// Plays in real time until it's told to quit, every sender of commands is dropped,
+    /// or a listener fails
+    pub fn run(mut self) -> anyhow::Result<()> {
+        self.sender = None;
+
+        loop {
+            self.play_due()?;
+
+            // Sleep until the next beat, polyrhythm pulse or timer tick is due, unless a
+            // command wakes us up first
+            let message = match self.next_wake_up() {
+                Some(timestamp) => self
+                    .receiver
+                    .recv_timeout(timestamp.saturating_duration_since(self.clock.now())),
+                None => self.receiver.recv().map_err(RecvTimeoutError::from),
+            };
+
+            match message {
+                Ok(user_input) => {
+                    let is_quit = matches!(user_input, UserInput::Quit);
+                    self.execute(user_input)?;
+
+                    if is_quit {
+                        return Ok(());
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::Cli;
+    use crate::clock::{Clock, ManualClock};
+    use crate::metronome::{
+        data::{
+            MetronomeData,
+            beat::{BeatInfo, accent::MetronomeBeatAccent},
+        },
+        engine::{Metronome, MetronomeEvent},
+    };
+    use crate::user_input::UserInput;
+    use clap::Parser;
+    use std::{
+        cell::RefCell,
+        iter,
+        rc::Rc,
+        time::{Duration, Instant},
+    };
+
+    /// A silent engine on a manual clock, which plays minutes in no time
+    struct Simulation {
+        clock: ManualClock,
+        start: Instant,
+        metronome: Metronome,
+        events: Rc<RefCell<Vec<MetronomeEvent>>>,
+    }
+
+    impl Simulation {
+        fn new(args: &[&str]) -> Self {
+            let cli = Cli::parse_from(iter::once("metronome").chain(args.iter().copied()));
+            let clock = ManualClock::new();
+            let mut metronome_data = MetronomeData::new(&cli.metronome_args).unwrap();
+            metronome_data.set_clock(clock.shared());
+
+            let mut metronome = Metronome::silent(metronome_data);
+            let events = Rc::new(RefCell::new(Vec::new()));
+            let listener_events = Rc::clone(&events);
+            metronome.on_event(move |event| {
+                listener_events.borrow_mut().push(event.clone());
+                Ok(())
+            });
+
+            Self {
+                start: clock.now(),
+                clock,
+                metronome,
+                events,
+            }
+        }
+
+        fn ms_since_start(&self, timestamp: Instant) -> u64 {
+            ((timestamp - self.start).as_secs_f64() * 1000.0).round() as u64
+        }
+
+        /// Moves the clock to `ms` after the start, waking the engine up whenever it
+        /// asks to be, like [`Metronome::run`] would
+        fn play_until(&mut self, ms: u64) {
+            let end = self.start + Duration::from_millis(ms);
+
+            while let Some(wake_up) = self.metronome.next_wake_up()
+                && wake_up <= end
+            {
+                self.clock.set(wake_up);
+                self.metronome.play_due().unwrap();
+            }
+            self.clock.set(end);
+        }
+
+        fn command(&mut self, command: &str) {
+            self.metronome.execute(command.parse().unwrap()).unwrap();
+        }
+
+        /// Every beat since the last call, with its time in milliseconds since the start
+        fn take_beats(&mut self) -> Vec<(u64, BeatInfo, Option<MetronomeBeatAccent>)> {
+            let events = self.events.take();
+
+            events
+                .into_iter()
+                .filter_map(|event| match event {
+                    MetronomeEvent::Beat {
+                        timestamp,
+                        beat_info,
+                        click,
+                    } => Some((self.ms_since_start(timestamp), beat_info, click)),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        fn take_beat_times(&mut self) -> Vec<u64> {
+            self.take_beats().into_iter().map(|(ms, _, _)| ms).collect()
+        }
+    }
+
+    #[test]
+    fn silent_engine_sends_beats_and_commands() {
+        let Simulation {
+            metronome, events, ..
+        } = Simulation::new(&["400"]);
+
+        // The clock never moves, so the first beat is played, the pause comes in, and
+        // the engine returns once it finds the sender dropped
+        metronome.sender().send(UserInput::Pause).unwrap();
+        let data = metronome.data();
+        metronome.run().unwrap();
+
+        let events = events.borrow();
+        assert!(matches!(
+            events.first(),
+            Some(MetronomeEvent::Beat { beat_info, click: Some(_), .. }) if *beat_info == (0, 0)
+        ));
+        assert!(matches!(
+            events.last(),
+            Some(MetronomeEvent::Command(UserInput::Pause))
+        ));
+        assert!(data.read().unwrap().is_paused);
+    }
+
+    #[test]
+    fn beats_stay_on_the_grid_for_minutes() {
+        let mut simulation = Simulation::new(&["150"]);
+        simulation.play_until(5 * 60 * 1000);
+
+        let beats = simulation.take_beats();
+        assert_eq!(beats.len(), 751);
+        for (i, (ms, beat_info, click)) in beats.into_iter().enumerate() {
+            assert_eq!(ms, i as u64 * 400);
+            assert_eq!(beat_info, (i as i32 % 4, 0));
+            assert!(click.is_some());
+        }
+    }
+
+    #[test]
+    fn tempo_change_mid_bar_continues_from_the_last_beat() {
+        let mut simulation = Simulation::new(&["60"]);
+        simulation.play_until(1500);
+        assert_eq!(simulation.take_beat_times(), [0, 1000]);
+
+        simulation.command("tempo 120");
+        simulation.play_until(3000);
+
+        let beats = simulation.take_beats();
+        let beat_times: Vec<u64> = beats.iter().map(|(ms, _, _)| *ms).collect();
+        let beat_numbers: Vec<i32> = beats
+            .iter()
+            .map(|(_, beat_info, _)| beat_info.current_beat)
+            .collect();
+        assert_eq!(beat_times, [1500, 2000, 2500, 3000]);
+        // The count starts over, as if the downbeat was played with the change
+        assert_eq!(beat_numbers, [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn resuming_restarts_the_grid() {
+        let mut simulation = Simulation::new(&["120"]);
+        simulation.play_until(1250);
+        assert_eq!(simulation.take_beat_times(), [0, 500, 1000]);
+
+        simulation.command("pause");
+        simulation.play_until(5000);
+        assert!(simulation.take_beat_times().is_empty());
+
+        simulation.command("resume");
+        simulation.play_until(6000);
+
+        let beats = simulation.take_beats();
+        let beat_times: Vec<u64> = beats.iter().map(|(ms, _, _)| *ms).collect();
+        assert_eq!(beat_times, [5000, 5500, 6000]);
+        assert_eq!(beats[0].1, (1, 0));
+    }
+
+    #[test]
+    fn subdivision_setting_silences_clicks() {
+        let mut simulation =
+            Simulation::new(&["60", "3/4", "-s", "3", "--subdivision-setting", "-x-"]);
+        simulation.play_until(2900);
+
+        let clicks: Vec<Option<MetronomeBeatAccent>> = simulation
+            .take_beats()
+            .into_iter()
+            .map(|(_, _, click)| click)
+            .collect();
+        // The downbeat always plays
+        assert_eq!(
+            clicks,
+            [
+                Some(MetronomeBeatAccent::Accented),
+                Some(MetronomeBeatAccent::Subdivision),
+                None,
+                None,
+                Some(MetronomeBeatAccent::Subdivision),
+                None,
+                None,
+                Some(MetronomeBeatAccent::Subdivision),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn timer_runs_out() {
+        let mut simulation = Simulation::new(&["60"]);
+        simulation.command("timer 0:03");
+
+        let time_remaining = |simulation: &Simulation| {
+            let data = simulation.metronome.data();
+            data.read()
+                .unwrap()
+                .timer
+                .as_ref()
+                .unwrap()
+                .time_remaining()
+        };
+
+        simulation.play_until(2500);
+        assert_eq!(time_remaining(&simulation), Duration::from_millis(500));
+
+        simulation.play_until(4000);
+        assert_eq!(time_remaining(&simulation), Duration::ZERO);
+
+        let timer_ticks = simulation
+            .events
+            .borrow()
+            .iter()
+            .filter(|event| matches!(event, MetronomeEvent::TimerTick))
+            .count();
+        assert_eq!(timer_ticks, 4);
+    }
+
+    #[test]
+    fn tapped_tempo_is_played() {
+        let mut simulation = Simulation::new(&["60"]);
+        simulation.command("tap");
+
+        for _ in 0..4 {
+            simulation.clock.advance(Duration::from_millis(400));
+            let tap = UserInput::TapBeat(simulation.clock.now());
+            simulation.metronome.execute(tap).unwrap();
+        }
+        assert_eq!(simulation.metronome.data().read().unwrap().tempo(), 150);
+
+        // Nothing plays while tapping
+        simulation.play_until(1600);
+        assert!(simulation.take_beat_times().is_empty());
+
+        simulation.command("resume");
+        simulation.play_until(2800);
+        assert_eq!(simulation.take_beat_times(), [1600, 2000, 2400, 2800]);
+    }
+
+    #[test]
+    fn quit_stops_the_engine() {
+        let Simulation {
+            metronome, events, ..
+        } = Simulation::new(&["120"]);
+
+        // The sender is kept, so only quitting can stop it
+        let sender = metronome.sender();
+        sender.send("tempo 1000".parse().unwrap()).unwrap();
+        sender.send(UserInput::Quit).unwrap();
+        metronome.run().unwrap();
+
+        let events = events.borrow();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            MetronomeEvent::CommandFailed { user_input: UserInput::SetTempo(_), error }
+                if error.starts_with("Invalid tempo `1000`!")
+        )));
+        assert!(matches!(
+            events.last(),
+            Some(MetronomeEvent::Command(UserInput::Quit))
+        ));
+    }
+}
@@ -0,0 +1,372 @@
+use anyhow::{Context, anyhow};
+use midly::{
+    Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
+    num::{u4, u7, u15, u24, u28},
+};
+use std::time::Duration;
+
+use crate::cli::MidiArgs;
+
+use super::{
+    data::{MetronomeData, beat::accent::MetronomeBeatAccent},
+    render::RenderLength,
+};
+
+/// Fine enough for every subdivision up to 8 to land close to its exact tick
+const TICKS_PER_QUARTER: u16 = 960;
+/// MIDI channel 10, which General MIDI reserves for percussion
+const PERCUSSION_CHANNEL: u8 = 9;
+/// How long each click note is held, which is a 64th note
+const NOTE_LENGTH_TICKS: u64 = TICKS_PER_QUARTER as u64 / 16;
+
+/// The General MIDI percussion note and the velocity of each accent, before the
+/// volume is applied
+fn click_note(accent: MetronomeBeatAccent) -> (u8, u8) {
+    match accent {
+        // Hi Wood Block
+        MetronomeBeatAccent::Accented => (76, 127),
+        // Low Wood Block
+        MetronomeBeatAccent::Beat => (77, 100),
+        MetronomeBeatAccent::Subdivision => (77, 70),
+        // Claves
+        MetronomeBeatAccent::Polyrhythm => (75, 90),
+    }
+}
+
+/// The key and velocity of a click, or `None` if its volume is 0
+fn click_key_and_velocity(
+    metronome_data: &MetronomeData,
+    accent: MetronomeBeatAccent,
+) -> Option<(u8, u8)> {
+    let (key, velocity) = click_note(accent);
+    let velocity = (velocity as f32 * metronome_data.volume.gain(accent)).round() as u8;
+
+    (velocity > 0).then_some((key, velocity))
+}
+
+fn note_events(tick: u64, key: u8, velocity: u8) -> [(u64, TrackEventKind<'static>); 2] {
+    let channel = u4::new(PERCUSSION_CHANNEL);
+    let key = u7::new(key);
+
+    [
+        (
+            tick,
+            TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn {
+                    key,
+                    vel: u7::new(velocity),
+                },
+            },
+        ),
+        (
+            tick + NOTE_LENGTH_TICKS,
+            TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOff {
+                    key,
+                    vel: u7::new(0),
+                },
+            },
+        ),
+    ]
+}
+
+/// Where an event goes among events on the same tick, so settings change before the
+/// notes and a note ends before the same note starts again
+fn event_order(kind: &TrackEventKind) -> u8 {
+    match kind {
+        TrackEventKind::Meta(_) => 0,
+        TrackEventKind::Midi {
+            message: MidiMessage::NoteOff { .. },
+            ..
+        } => 1,
+        _ => 2,
+    }
+}
+
+/// Walks through the beats of `metronome_data` like a render does, and returns the
+/// tempo, time signature and note events of the click map at their tick, along with
+/// the tick the map ends on. Fails on settings a MIDI file can't hold
+pub fn click_map_events(
+    metronome_data: &mut MetronomeData,
+    render_length: RenderLength,
+) -> anyhow::Result<(Vec<(u64, TrackEventKind<'static>)>, u64)> {
+    let mut events = Vec::new();
+    let mut notes = Vec::new();
+    let mut quarters = 0.0;
+    let mut elapsed = Duration::ZERO;
+    let mut bars_started = 0;
+    let mut last_tempo = None;
+    let mut last_time_signature = None;
+
+    let tick_of_quarters = |quarters: f64| (quarters * TICKS_PER_QUARTER as f64).round() as u64;
+
+    loop {
+        metronome_data.move_to_next_subdivided_beat();
+
+        // A program pauses the metronome once it finishes
+        if metronome_data.is_paused {
+            break;
+        }
+        if metronome_data.beat_info == (0, 0) {
+            bars_started += 1;
+        }
+        match render_length {
+            RenderLength::Bars(bars) if bars_started > bars => break,
+            RenderLength::Duration(duration) if elapsed >= duration => break,
+            _ => {}
+        }
+
+        let tick = tick_of_quarters(quarters);
+        let time_signature = metronome_data.time_signature();
+        let note_length = metronome_data.tempo_type().to_note_length();
+
+        let micros_per_quarter =
+            (60_000_000.0 / (metronome_data.tempo() as f64 * note_length)).round() as u32;
+        if last_tempo != Some(micros_per_quarter) {
+            last_tempo = Some(micros_per_quarter);
+
+            let micros_per_quarter = u24::try_from(micros_per_quarter).ok_or_else(|| {
+                anyhow!(
+                    "{} = {} is too slow for a MIDI file!",
+                    metronome_data.tempo_type(),
+                    metronome_data.tempo()
+                )
+            })?;
+            events.push((
+                tick,
+                TrackEventKind::Meta(MetaMessage::Tempo(micros_per_quarter)),
+            ));
+        }
+
+        if last_time_signature != Some((time_signature.0, time_signature.1)) {
+            last_time_signature = Some((time_signature.0, time_signature.1));
+
+            let numerator = u8::try_from(time_signature.0).map_err(|_| {
+                anyhow!(
+                    "{} has too many beats for a MIDI file!",
+                    metronome_data.time_signature()
+                )
+            })?;
+            // The metronome of the MIDI file clicks once per tempo unit, which is 24
+            // MIDI clocks for a quarter note
+            events.push((
+                tick,
+                TrackEventKind::Meta(MetaMessage::TimeSignature(
+                    numerator,
+                    time_signature.1.trailing_zeros() as u8,
+                    (24.0 * note_length).round() as u8,
+                    8,
+                )),
+            ));
+        }
+
+        if let Some((key, velocity)) = metronome_data
+            .current_click()
+            .and_then(|accent| click_key_and_velocity(metronome_data, accent))
+        {
+            notes.push((tick, key, velocity));
+        }
+
+        let quarters_per_subdivided_beat =
+            4.0 / time_signature.1 as f64 / metronome_data.subdivision() as f64;
+
+        if let Some((polyrhythm, span)) = metronome_data.polyrhythm_span()
+            && !metronome_data.is_muted()
+            && let Some((key, velocity)) =
+                click_key_and_velocity(metronome_data, MetronomeBeatAccent::Polyrhythm)
+        {
+            let span_quarters = span.as_secs_f64()
+                / metronome_data.duration_per_subdivided_beat().as_secs_f64()
+                * quarters_per_subdivided_beat;
+
+            for pulse in 0..polyrhythm.pulses {
+                let pulse_quarters =
+                    quarters + span_quarters * pulse as f64 / polyrhythm.pulses as f64;
+                notes.push((tick_of_quarters(pulse_quarters), key, velocity));
+            }
+        }
+
+        quarters += quarters_per_subdivided_beat;
+        elapsed += metronome_data.duration_per_subdivided_beat();
+    }
+
+    let end_tick = tick_of_quarters(quarters);
+
+    // Pulses of a polyrhythm span can go past the end
+    events.extend(
+        notes
+            .into_iter()
+            .filter(|(tick, _, _)| *tick < end_tick)
+            .flat_map(|(tick, key, velocity)| note_events(tick, key, velocity)),
+    );
+    events.sort_by_key(|(tick, kind)| (*tick, event_order(kind)));
+
+    Ok((events, end_tick))
+}
+
+pub fn write_midi_file(midi_args: &MidiArgs) -> anyhow::Result<()> {
+    let mut metronome_data = MetronomeData::new(&midi_args.metronome_args)?;
+    if let Some(ref program_path) = midi_args.program {
+        metronome_data.load_program_file(program_path)?;
+    }
+
+    let render_length = match (midi_args.bars, midi_args.duration) {
+        (Some(bars), _) => RenderLength::Bars(bars),
+        (None, Some(duration)) => RenderLength::Duration(duration),
+        (None, None) => unreachable!("clap requires either bars or a duration"),
+    };
+
+    let (events, end_tick) = click_map_events(&mut metronome_data, render_length)?;
+
+    let mut track = Vec::with_capacity(events.len() + 1);
+    let mut last_tick = 0;
+
+    for (tick, kind) in events {
+        track.push(TrackEvent {
+            delta: u28::new((tick - last_tick) as u32),
+            kind,
+        });
+        last_tick = tick;
+    }
+    track.push(TrackEvent {
+        delta: u28::new(end_tick.saturating_sub(last_tick) as u32),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+
+    let mut smf = Smf::new(Header::new(
+        Format::SingleTrack,
+        Timing::Metrical(u15::new(TICKS_PER_QUARTER)),
+    ));
+    smf.tracks.push(track);
+
+    smf.save(&midi_args.output)
+        .with_context(|| format!("Failed to write {}", midi_args.output.display()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{click_map_events, event_order};
+    use crate::cli::Cli;
+    use crate::metronome::{
+        data::{MetronomeData, program::Program},
+        render::RenderLength,
+    };
+    use clap::Parser;
+    use midly::{MetaMessage, MidiMessage, TrackEventKind};
+    use std::str::FromStr;
+
+    fn metronome_data(args: &[&str]) -> MetronomeData {
+        let cli = Cli::parse_from(std::iter::once("metronome").chain(args.iter().copied()));
+        MetronomeData::new(&cli.metronome_args).unwrap()
+    }
+
+    fn note_ons(events: &[(u64, TrackEventKind)]) -> Vec<(u64, u8, u8)> {
+        events
+            .iter()
+            .filter_map(|(tick, kind)| match kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, vel },
+                    ..
+                } => Some((*tick, key.as_int(), vel.as_int())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn meta_events<'a>(events: &'a [(u64, TrackEventKind<'a>)]) -> Vec<(u64, MetaMessage<'a>)> {
+        events
+            .iter()
+            .filter_map(|(tick, kind)| match kind {
+                TrackEventKind::Meta(meta_message) => Some((*tick, *meta_message)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn click_map_follows_settings() {
+        let mut data = metronome_data(&["60", "6/8", "-s", "2", "--subdivision-setting", "x-"]);
+        let (events, end_tick) = click_map_events(&mut data, RenderLength::Bars(1)).unwrap();
+
+        // 6/8 is in dotted quarters, so 60 BPM is 1 second per dotted quarter
+        assert_eq!(
+            meta_events(&events),
+            [
+                (0, MetaMessage::Tempo(666_667.into())),
+                (0, MetaMessage::TimeSignature(6, 3, 36, 8))
+            ]
+        );
+        assert_eq!(
+            note_ons(&events),
+            [
+                (0, 76, 127),
+                (480, 77, 70),
+                (960, 77, 70),
+                (1440, 77, 100),
+                (1920, 77, 70),
+                (2400, 77, 70)
+            ]
+        );
+        assert_eq!(end_tick, 2880);
+        assert!(events.is_sorted_by_key(|(tick, kind)| (*tick, event_order(kind))));
+    }
+
+    #[test]
+    fn program_changes_are_written() {
+        let mut data = metronome_data(&[]);
+        data.load_program(
+            Program::from_str(
+                r#"
+                [[section]]
+                name = "A"
+                bars = 1
+                tempo = 120
+                time_signature = "2/4"
+
+                [[section]]
+                name = "B"
+                bars = 1
+                tempo = 90
+                time_signature = "3/4"
+                "#,
+            )
+            .unwrap(),
+        );
+        let (events, end_tick) = click_map_events(&mut data, RenderLength::Bars(10)).unwrap();
+
+        assert_eq!(
+            meta_events(&events),
+            [
+                (0, MetaMessage::Tempo(500_000.into())),
+                (0, MetaMessage::TimeSignature(2, 2, 24, 8)),
+                (1920, MetaMessage::Tempo(666_667.into())),
+                (1920, MetaMessage::TimeSignature(3, 2, 24, 8))
+            ]
+        );
+        assert_eq!(note_ons(&events).len(), 5);
+        assert_eq!(end_tick, 1920 + 3 * 960);
+    }
+
+    #[test]
+    fn settings_a_midi_file_cant_hold() {
+        // 2.4 seconds per sixteenth note is 24,000,000 µs per quarter, which is more
+        // than the 24 bits a MIDI tempo has
+        let mut data = metronome_data(&["10", "--tempo-type", "sixteenth-note"]);
+        let error = click_map_events(&mut data, RenderLength::Bars(1)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Sixteenth Note = 10 is too slow for a MIDI file!"
+        );
+
+        let mut data = metronome_data(&["120", "300/4"]);
+        let error = click_map_events(&mut data, RenderLength::Bars(1)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "300/4 has too many beats for a MIDI file!"
+        );
+    }
+}
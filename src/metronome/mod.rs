@@ -1,4 +1,5 @@
 pub mod data;
+pub mod engine;
+pub mod midi;
+pub mod render;
 pub mod sound;
-
-mod help_menu;
@@ -0,0 +1,355 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use rodio::{ChannelCount, SampleRate, Source};
+
+use crate::cli::RenderArgs;
+
+use super::{
+    data::{MetronomeData, beat::accent::MetronomeBeatAccent},
+    sound::{ClickMixer, MetronomeSounds, MixerMessage, ScheduledClick, SoundSlot},
+};
+
+const RENDER_CHANNELS: ChannelCount = 2;
+const RENDER_SAMPLE_RATE: SampleRate = 48000;
+
+/// How long the click track is, either in bars or in time
+#[derive(Debug, Clone, Copy)]
+pub enum RenderLength {
+    Bars(u32),
+    Duration(Duration),
+}
+
+/// The click mixer cut off after an exact number of frames
+struct ClickTrack {
+    click_mixer: ClickMixer,
+    samples_left: u64,
+}
+
+impl ClickTrack {
+    /// Plays `clicks` with `sounds` for `total_frames` frames
+    fn new(
+        sounds: MetronomeSounds,
+        clicks: Vec<ScheduledClick>,
+        total_frames: u64,
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+    ) -> anyhow::Result<Self> {
+        let (click_mixer, sender) = ClickMixer::new(sounds, channels, sample_rate);
+
+        for click in clicks {
+            sender.send(MixerMessage::Click(click))?;
+        }
+
+        Ok(Self {
+            click_mixer,
+            samples_left: total_frames * channels as u64,
+        })
+    }
+}
+
+impl Iterator for ClickTrack {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.samples_left = self.samples_left.checked_sub(1)?;
+        self.click_mixer.next()
+    }
+}
+
+impl Source for ClickTrack {
+    fn current_span_len(&self) -> Option<usize> {
+        Some(self.samples_left as usize)
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.click_mixer.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.click_mixer.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Walks through the beat grid of `metronome_data` and returns every click that
+/// should be heard, along with the total number of frames rendered
+pub fn schedule_clicks(
+    metronome_data: &mut MetronomeData,
+    render_length: RenderLength,
+    sample_rate: SampleRate,
+) -> (Vec<ScheduledClick>, u64) {
+    let secs_per_subdivided_beat = metronome_data.duration_per_subdivided_beat().as_secs_f64();
+    let frame_of_secs = |secs: f64| (secs * sample_rate as f64).round() as u64;
+    let frame_of_beat = |beat: u64| frame_of_secs(beat as f64 * secs_per_subdivided_beat);
+
+    let num_subdivided_beats = match render_length {
+        RenderLength::Bars(bars) => {
+            bars as u64
+                * metronome_data.time_signature().0 as u64
+                * metronome_data.subdivision() as u64
+        }
+        RenderLength::Duration(duration) => {
+            (duration.as_secs_f64() / secs_per_subdivided_beat).ceil() as u64
+        }
+    };
+    let total_frames = match render_length {
+        RenderLength::Bars(_) => frame_of_beat(num_subdivided_beats),
+        RenderLength::Duration(duration) => {
+            (duration.as_secs_f64() * sample_rate as f64).round() as u64
+        }
+    };
+
+    let mut clicks = Vec::new();
+
+    for beat in 0..num_subdivided_beats {
+        metronome_data.move_to_next_subdivided_beat();
+
+        if let Some(accent) = metronome_data.current_click() {
+            clicks.push(ScheduledClick {
+                frame: frame_of_beat(beat),
+                accent,
+                pan: metronome_data.click_pan(accent),
+                gain: metronome_data.volume.gain(accent),
+            });
+        }
+
+        if let Some((polyrhythm, span)) = metronome_data.polyrhythm_span()
+            && !metronome_data.is_muted()
+        {
+            let accent = MetronomeBeatAccent::Polyrhythm;
+            let span_start = beat as f64 * secs_per_subdivided_beat;
+
+            let pulses = (0..polyrhythm.pulses)
+                .map(|pulse| ScheduledClick {
+                    frame: frame_of_secs(
+                        span_start + span.as_secs_f64() * pulse as f64 / polyrhythm.pulses as f64,
+                    ),
+                    accent,
+                    pan: metronome_data.click_pan(accent),
+                    gain: metronome_data.volume.gain(accent),
+                })
+                .filter(|click| click.frame < total_frames);
+            clicks.extend(pulses);
+        }
+    }
+
+    clicks.sort_by_key(|click| click.frame);
+
+    (clicks, total_frames)
+}
+
+pub fn render_click_track(render_args: &RenderArgs) -> anyhow::Result<()> {
+    let mut metronome_data = MetronomeData::new(&render_args.metronome_args)?;
+
+    let render_length = match (render_args.bars, render_args.duration) {
+        (Some(bars), _) => RenderLength::Bars(bars),
+        (None, Some(duration)) => RenderLength::Duration(duration),
+        (None, None) => unreachable!("clap requires either bars or a duration"),
+    };
+
+    let (clicks, total_frames) =
+        schedule_clicks(&mut metronome_data, render_length, RENDER_SAMPLE_RATE);
+
+    let mut sounds = MetronomeSounds::decode(RENDER_CHANNELS, RENDER_SAMPLE_RATE)?;
+    for (accent, sound_source) in render_args.sound_args.sound_sources() {
+        let samples = sound_source
+            .load(RENDER_CHANNELS, RENDER_SAMPLE_RATE)
+            .with_context(|| format!("Couldn't load the {} sound", SoundSlot::Click(accent)))?;
+        sounds.set(accent, samples);
+    }
+
+    let mut click_track = ClickTrack::new(
+        sounds,
+        clicks,
+        total_frames,
+        RENDER_CHANNELS,
+        RENDER_SAMPLE_RATE,
+    )?;
+
+    rodio::output_to_wav(&mut click_track, &render_args.output).map_err(|err| {
+        anyhow::anyhow!("Failed to write {}: {}", render_args.output.display(), err)
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClickTrack, RenderLength, schedule_clicks};
+    use crate::cli::Cli;
+    use crate::metronome::{
+        data::{MetronomeData, beat::accent::MetronomeBeatAccent},
+        sound::MetronomeSounds,
+    };
+    use clap::Parser;
+    use std::time::Duration;
+
+    fn metronome_data(args: &[&str]) -> MetronomeData {
+        let cli = Cli::parse_from(std::iter::once("metronome").chain(args.iter().copied()));
+        MetronomeData::new(&cli.metronome_args).unwrap()
+    }
+
+    #[test]
+    fn clicks_fall_on_exact_frames() {
+        let mut data = metronome_data(&["120", "4/4"]);
+        let (clicks, total_frames) = schedule_clicks(&mut data, RenderLength::Bars(2), 48000);
+
+        assert_eq!(total_frames, 8 * 24000);
+        assert_eq!(clicks.len(), 8);
+
+        for (i, click) in clicks.iter().enumerate() {
+            assert_eq!(click.frame, i as u64 * 24000);
+        }
+
+        assert_eq!(clicks[0].accent, MetronomeBeatAccent::Accented);
+        assert_eq!(clicks[1].accent, MetronomeBeatAccent::Beat);
+        assert_eq!(clicks[4].accent, MetronomeBeatAccent::Accented);
+    }
+
+    #[test]
+    fn subdivision_setting_silences_clicks() {
+        let mut data = metronome_data(&["60", "4/4", "-s", "2", "--subdivision-setting", "x-"]);
+        let (clicks, _) = schedule_clicks(&mut data, RenderLength::Bars(1), 48000);
+
+        let frames: Vec<u64> = clicks.iter().map(|click| click.frame).collect();
+        assert_eq!(frames, [0, 48000, 96000, 144000]);
+    }
+
+    #[test]
+    fn custom_accents_are_rendered() {
+        use MetronomeBeatAccent::*;
+
+        let mut data = metronome_data(&["120", "7/8", "--accents", "X.x.xx."]);
+        let (clicks, _) = schedule_clicks(&mut data, RenderLength::Bars(1), 48000);
+
+        let accents: Vec<MetronomeBeatAccent> = clicks.iter().map(|click| click.accent).collect();
+        assert_eq!(
+            accents,
+            [
+                Accented,
+                Subdivision,
+                Beat,
+                Subdivision,
+                Beat,
+                Beat,
+                Subdivision
+            ]
+        );
+    }
+
+    #[test]
+    fn additive_time_signature_drives_accents_and_subdivision_setting() {
+        use MetronomeBeatAccent::*;
+
+        let mut data = metronome_data(&["120", "2+2+3/8", "--subdivision-setting", "x--"]);
+        let (clicks, _) = schedule_clicks(&mut data, RenderLength::Bars(1), 6000);
+
+        let clicks: Vec<(u64, MetronomeBeatAccent)> = clicks
+            .iter()
+            .map(|click| (click.frame, click.accent))
+            .collect();
+        assert_eq!(clicks, [(0, Accented), (2000, Beat), (4000, Beat)]);
+    }
+
+    #[test]
+    fn gap_click_mutes_bars_without_moving_the_beats() {
+        let mut data = metronome_data(&["120", "2/4", "--gap", "1 1", "--poly", "3 bar"]);
+        let (clicks, _) = schedule_clicks(&mut data, RenderLength::Bars(4), 48000);
+
+        let frames: Vec<u64> = clicks
+            .iter()
+            .filter(|click| click.accent != MetronomeBeatAccent::Polyrhythm)
+            .map(|click| click.frame)
+            .collect();
+        assert_eq!(frames, [0, 24000, 96000, 120000]);
+        assert_eq!(clicks.len(), 10);
+    }
+
+    #[test]
+    fn polyrhythm_layer_is_rendered() {
+        use MetronomeBeatAccent::*;
+
+        let mut data = metronome_data(&["60", "2/4", "--poly", "3 bar pan"]);
+        let (clicks, _) = schedule_clicks(&mut data, RenderLength::Bars(1), 6000);
+
+        let clicks: Vec<(u64, MetronomeBeatAccent, f32)> = clicks
+            .iter()
+            .map(|click| (click.frame, click.accent, click.pan))
+            .collect();
+        assert_eq!(
+            clicks,
+            [
+                (0, Accented, -0.7),
+                (0, Polyrhythm, 0.7),
+                (4000, Polyrhythm, 0.7),
+                (6000, Beat, -0.7),
+                (8000, Polyrhythm, 0.7)
+            ]
+        );
+    }
+
+    #[test]
+    fn volumes_are_rendered() {
+        let mut data = metronome_data(&[
+            "60",
+            "2/4",
+            "-s",
+            "2",
+            "--volume",
+            "50",
+            "--subdivision-volume",
+            "20",
+        ]);
+        let (clicks, _) = schedule_clicks(&mut data, RenderLength::Bars(1), 1000);
+
+        let gains: Vec<f32> = clicks.iter().map(|click| click.gain).collect();
+        assert_eq!(gains, [0.5, 0.1, 0.5, 0.1]);
+    }
+
+    #[test]
+    fn long_tracks_are_mixed_click_by_click() {
+        let mut data = metronome_data(&["200", "-s", "4"]);
+        let (clicks, total_frames) = schedule_clicks(
+            &mut data,
+            RenderLength::Duration(Duration::from_secs(30 * 60)),
+            1000,
+        );
+        let click_frames: Vec<u64> = clicks.iter().map(|click| click.frame).collect();
+
+        // Every click is a single sample, so each one shows up on its own frame
+        let mut sounds = MetronomeSounds::decode(1, 1000).unwrap();
+        for accent in [
+            MetronomeBeatAccent::Accented,
+            MetronomeBeatAccent::Beat,
+            MetronomeBeatAccent::Subdivision,
+        ] {
+            sounds.set(accent, vec![1.0]);
+        }
+        let click_track = ClickTrack::new(sounds, clicks, total_frames, 1, 1000).unwrap();
+
+        let heard_frames: Vec<u64> = (0..)
+            .zip(click_track)
+            .filter(|(_, sample)| *sample != 0.0)
+            .map(|(frame, _)| frame)
+            .collect();
+        assert_eq!(heard_frames.len(), 200 * 4 * 30);
+        assert_eq!(heard_frames, click_frames);
+    }
+
+    #[test]
+    fn duration_rounds_up_to_whole_beats() {
+        let mut data = metronome_data(&["60"]);
+        let (clicks, total_frames) = schedule_clicks(
+            &mut data,
+            RenderLength::Duration(Duration::from_millis(2500)),
+            1000,
+        );
+
+        assert_eq!(total_frames, 2500);
+        assert_eq!(clicks.len(), 3);
+    }
+}
@@ -1,6 +1,24 @@
-use std::io::Cursor;
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::File,
+    io::Cursor,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex, mpsc},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, anyhow};
+use rodio::{
+    ChannelCount, Decoder, OutputStream, OutputStreamBuilder, SampleRate, Source,
+    buffer::SamplesBuffer, mixer::Mixer, source::UniformSourceIterator,
+};
 
 use super::data::beat::accent::MetronomeBeatAccent;
+use synth::SynthClick;
+
+pub mod synth;
 
 static METRONOME_SOUNDS: &[&[u8]] = &[
     include_bytes!("../audio/beat1.mp3"),
@@ -8,16 +26,578 @@ static METRONOME_SOUNDS: &[&[u8]] = &[
     include_bytes!("../audio/beat3.mp3"),
 ];
 
-pub fn play_metronome_sound(
-    metronome_beat_accent: MetronomeBeatAccent,
-) -> anyhow::Result<(rodio::OutputStream, rodio::Sink)> {
-    let mut stream_handler = rodio::OutputStreamBuilder::open_default_stream()?;
-    stream_handler.log_on_drop(false);
+/// The polyrhythm layer plays the normal beat sound sped up by a fifth, so it can be
+/// told apart from the main layer
+const POLYRHYTHM_SOUND_INDEX: usize = 1;
+const POLYRHYTHM_SOUND_SPEED: f32 = 1.5;
+
+/// Every accent that has its own click sound, in the order they're stored
+const CLICK_ACCENTS: [MetronomeBeatAccent; 4] = [
+    MetronomeBeatAccent::Accented,
+    MetronomeBeatAccent::Beat,
+    MetronomeBeatAccent::Subdivision,
+    MetronomeBeatAccent::Polyrhythm,
+];
+
+/// A sound that can be replaced with a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundSlot {
+    Click(MetronomeBeatAccent),
+    Alarm,
+}
+
+impl FromStr for SoundSlot {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "accented" => Ok(Self::Click(MetronomeBeatAccent::Accented)),
+            "beat" => Ok(Self::Click(MetronomeBeatAccent::Beat)),
+            "subdivision" => Ok(Self::Click(MetronomeBeatAccent::Subdivision)),
+            "poly" => Ok(Self::Click(MetronomeBeatAccent::Polyrhythm)),
+            "alarm" => Ok(Self::Alarm),
+            _ => Err(anyhow!(
+                "Invalid sound `{}`! Must be accented, beat, subdivision, poly or alarm",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SoundSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let string = match self {
+            Self::Click(MetronomeBeatAccent::Accented) => "accented",
+            Self::Click(MetronomeBeatAccent::Beat) => "beat",
+            Self::Click(MetronomeBeatAccent::Subdivision) => "subdivision",
+            Self::Click(MetronomeBeatAccent::Polyrhythm) => "poly",
+            Self::Alarm => "alarm",
+        };
+
+        write!(f, "{}", string)
+    }
+}
+
+/// Decodes a sound file into the format of the output. WAV, OGG, FLAC and MP3 files
+/// are supported
+pub fn decode_sound_file(
+    path: &Path,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+) -> anyhow::Result<Vec<f32>> {
+    let file = File::open(path).with_context(|| format!("Failed to open `{}`", path.display()))?;
+    let decoder = Decoder::try_from(file)
+        .with_context(|| format!("Failed to decode `{}`", path.display()))?;
+
+    let samples: Vec<f32> = UniformSourceIterator::new(decoder, channels, sample_rate).collect();
+
+    if samples.is_empty() {
+        return Err(anyhow!("`{}` has no audio in it", path.display()));
+    }
+
+    Ok(samples)
+}
+
+/// Where a sound comes from, other than the built-in sounds
+#[derive(Debug, Clone, PartialEq)]
+pub enum SoundSource {
+    File(PathBuf),
+    Synth(SynthClick),
+}
+
+impl SoundSource {
+    /// Decodes or synthesizes the sound in the format of the output
+    pub fn load(
+        &self,
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+    ) -> anyhow::Result<Vec<f32>> {
+        match self {
+            Self::File(path) => decode_sound_file(path, channels, sample_rate),
+            Self::Synth(synth_click) => Ok(synth_click.synthesize(channels, sample_rate)),
+        }
+    }
+}
+
+impl FromStr for SoundSource {
+    type Err = anyhow::Error;
+
+    /// Parses either `synth <WAVEFORM> [PITCH] [DECAY] [LENGTH]`, or the path to a file
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("synth") {
+            Some(synth_click) if synth_click.is_empty() || synth_click.starts_with(' ') => {
+                Ok(Self::Synth(synth_click.parse()?))
+            }
+            _ => Ok(Self::File(PathBuf::from(s))),
+        }
+    }
+}
+
+fn decode_built_in_click(
+    accent: MetronomeBeatAccent,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+) -> anyhow::Result<Vec<f32>> {
+    let (audio_data, speed) = match accent {
+        MetronomeBeatAccent::Polyrhythm => (
+            METRONOME_SOUNDS[POLYRHYTHM_SOUND_INDEX],
+            POLYRHYTHM_SOUND_SPEED,
+        ),
+        _ => (METRONOME_SOUNDS[accent as usize], 1.0),
+    };
+
+    let decoder = Decoder::new(Cursor::new(audio_data))?.speed(speed);
+    Ok(UniformSourceIterator::new(decoder, channels, sample_rate).collect())
+}
+
+/// How long after its beat a click is actually heard. The main loop hands each click
+/// to the audio thread ahead of time, so as long as it wakes up within this window,
+/// every click lands on its exact sample
+pub const AUDIO_LATENCY: Duration = Duration::from_millis(50);
+
+/// How often the mixer says which frame it's on, in frames
+const MIXER_POSITION_INTERVAL: u64 = 256;
+/// How much of the difference to each new mixer position is taken over. The mixer
+/// fills the output a buffer at a time, so single positions are off by up to a buffer,
+/// while the sound card's clock only drifts slowly
+const FRAME_CLOCK_SMOOTHING: f64 = 0.02;
+
+/// The metronome sounds, decoded once into the format of the output, indexed by
+/// `MetronomeBeatAccent`
+pub struct MetronomeSounds {
+    samples: Vec<Vec<f32>>,
+}
+
+impl MetronomeSounds {
+    /// Decodes the built-in sounds
+    pub fn decode(channels: ChannelCount, sample_rate: SampleRate) -> anyhow::Result<Self> {
+        let samples = CLICK_ACCENTS
+            .into_iter()
+            .map(|accent| decode_built_in_click(accent, channels, sample_rate))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { samples })
+    }
+
+    pub fn set(&mut self, metronome_beat_accent: MetronomeBeatAccent, samples: Vec<f32>) {
+        self.samples[metronome_beat_accent as usize] = samples;
+    }
+
+    fn get(&self, metronome_beat_accent: MetronomeBeatAccent) -> &[f32] {
+        &self.samples[metronome_beat_accent as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScheduledClick {
+    pub frame: u64,
+    pub accent: MetronomeBeatAccent,
+    /// Where the click is heard, from -1 (left) to 1 (right)
+    pub pan: f32,
+    /// How loud the click is, with 1 being unchanged
+    pub gain: f32,
+}
+
+/// A frame the mixer started, and when it did
+#[derive(Debug, Clone, Copy)]
+pub struct MixerPosition {
+    pub frame: u64,
+    pub at: Instant,
+}
+
+/// Turns instants into frames of the output. The sound card's clock drifts away from
+/// the system clock, so this follows the frames the mixer has actually started
+#[derive(Debug)]
+struct FrameClock {
+    sample_rate: SampleRate,
+    started_at: Instant,
+    /// The frame the mixer was on at `started_at`, as far as its positions tell
+    start_frame: Option<f64>,
+}
+
+impl FrameClock {
+    fn new(sample_rate: SampleRate, started_at: Instant) -> Self {
+        Self {
+            sample_rate,
+            started_at,
+            start_frame: None,
+        }
+    }
+
+    fn frames_since_start(&self, instant: Instant) -> f64 {
+        instant
+            .saturating_duration_since(self.started_at)
+            .as_secs_f64()
+            * self.sample_rate as f64
+    }
+
+    /// Moves the estimate a bit towards where `mixer_position` says the mixer is
+    fn correct(&mut self, mixer_position: MixerPosition) {
+        let start_frame = mixer_position.frame as f64 - self.frames_since_start(mixer_position.at);
+
+        self.start_frame = Some(match self.start_frame {
+            Some(estimate) => estimate + (start_frame - estimate) * FRAME_CLOCK_SMOOTHING,
+            None => start_frame,
+        });
+    }
+
+    /// The frame that's played at `instant`
+    fn frame_at(&self, instant: Instant) -> u64 {
+        (self.start_frame.unwrap_or_default() + self.frames_since_start(instant))
+            .round()
+            .max(0.0) as u64
+    }
+}
+
+/// What the audio thread can be told to do
+pub enum MixerMessage {
+    Click(ScheduledClick),
+    SetSound(MetronomeBeatAccent, Vec<f32>),
+}
+
+struct Voice {
+    accent: MetronomeBeatAccent,
+    start_frame: u64,
+    pan: f32,
+    gain: f32,
+}
+
+/// How loud a click panned to `pan` is on `channel`. Only stereo output is panned
+fn pan_gain(pan: f32, channel: ChannelCount, channels: ChannelCount) -> f32 {
+    match (channels, channel) {
+        (2, 0) => (1.0 - pan).min(1.0),
+        (2, 1) => (1.0 + pan).min(1.0),
+        _ => 1.0,
+    }
+}
+
+/// A never ending `Source` that mixes the metronome sounds in at the exact frame
+/// each click was scheduled for
+pub struct ClickMixer {
+    sounds: MetronomeSounds,
+    receiver: mpsc::Receiver<MixerMessage>,
+    /// Clicks that haven't started yet, sorted by their frame
+    pending: VecDeque<ScheduledClick>,
+    voices: Vec<Voice>,
+    /// The last frame started, for whoever schedules clicks to line up with
+    position: Arc<Mutex<Option<MixerPosition>>>,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    frame: u64,
+    channel: ChannelCount,
+}
+
+impl ClickMixer {
+    pub fn new(
+        sounds: MetronomeSounds,
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+    ) -> (Self, mpsc::Sender<MixerMessage>) {
+        let (sender, receiver) = mpsc::channel();
+
+        let click_mixer = Self {
+            sounds,
+            receiver,
+            pending: VecDeque::new(),
+            voices: Vec::new(),
+            position: Arc::new(Mutex::new(None)),
+            channels,
+            sample_rate,
+            frame: 0,
+            channel: 0,
+        };
+
+        (click_mixer, sender)
+    }
+
+    /// Where the mixer is, updated every `MIXER_POSITION_INTERVAL` frames once it plays
+    pub fn position(&self) -> Arc<Mutex<Option<MixerPosition>>> {
+        Arc::clone(&self.position)
+    }
+
+    /// Picks up newly scheduled clicks, starts the ones that are due and drops the
+    /// ones that finished playing
+    fn start_frame(&mut self) {
+        let Self {
+            sounds,
+            receiver,
+            pending,
+            voices,
+            position,
+            channels,
+            frame,
+            ..
+        } = self;
+
+        if *frame % MIXER_POSITION_INTERVAL == 0 {
+            *position.lock().unwrap() = Some(MixerPosition {
+                frame: *frame,
+                at: Instant::now(),
+            });
+        }
+
+        for message in receiver.try_iter() {
+            match message {
+                MixerMessage::Click(click) => {
+                    // Clicks mostly come in order, so this is usually the end
+                    let index = pending.partition_point(|pending| pending.frame <= click.frame);
+                    pending.insert(index, click);
+                }
+                MixerMessage::SetSound(accent, samples) => {
+                    // Clicks still playing the old sound are cut off, since they index
+                    // into its samples
+                    voices.retain(|voice| voice.accent != accent);
+                    sounds.set(accent, samples);
+                }
+            }
+        }
+
+        voices.retain(|voice| {
+            ((*frame - voice.start_frame) as usize * *channels as usize)
+                < sounds.get(voice.accent).len()
+        });
+
+        // A click that arrived too late still gets played, just as soon as possible
+        while let Some(click) = pending.front().copied()
+            && click.frame <= *frame
+        {
+            pending.pop_front();
+            voices.push(Voice {
+                accent: click.accent,
+                start_frame: *frame,
+                pan: click.pan,
+                gain: click.gain,
+            });
+        }
+    }
+}
+
+impl Iterator for ClickMixer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.channel == 0 {
+            self.start_frame();
+        }
+
+        let sample = self
+            .voices
+            .iter()
+            .map(|voice| {
+                let index = (self.frame - voice.start_frame) as usize * self.channels as usize
+                    + self.channel as usize;
+
+                let sample = self
+                    .sounds
+                    .get(voice.accent)
+                    .get(index)
+                    .copied()
+                    .unwrap_or_default();
+
+                sample * voice.gain * pan_gain(voice.pan, self.channel, self.channels)
+            })
+            .sum::<f32>()
+            // Clicks that overlap shouldn't wrap around or blow up the output
+            .clamp(-1.0, 1.0);
+
+        self.channel += 1;
+        if self.channel == self.channels {
+            self.channel = 0;
+            self.frame += 1;
+        }
+
+        Some(sample)
+    }
+}
+
+impl Source for ClickMixer {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// One output stream that stays open for the whole session. Clicks are scheduled on
+/// it by the instant of their beat instead of opening a new stream for every click
+pub struct AudioEngine {
+    stream: OutputStream,
+    sender: mpsc::Sender<MixerMessage>,
+    mixer_position: Arc<Mutex<Option<MixerPosition>>>,
+    frame_clock: FrameClock,
+    custom_alarm: Option<SamplesBuffer>,
+}
+
+impl AudioEngine {
+    pub fn new() -> anyhow::Result<Self> {
+        let mut stream = OutputStreamBuilder::open_default_stream()?;
+        stream.log_on_drop(false);
+
+        let channels = stream.config().channel_count();
+        let sample_rate = stream.config().sample_rate();
+
+        let (click_mixer, sender) = ClickMixer::new(
+            MetronomeSounds::decode(channels, sample_rate)?,
+            channels,
+            sample_rate,
+        );
+        let mixer_position = click_mixer.position();
+        stream.mixer().add(click_mixer);
+
+        Ok(Self {
+            stream,
+            sender,
+            mixer_position,
+            frame_clock: FrameClock::new(sample_rate, Instant::now()),
+            custom_alarm: None,
+        })
+    }
+
+    /// Replaces a sound with a file or a synthesized click, or goes back to the
+    /// built-in sound if `sound_source` is `None`. If the sound can't be loaded, the
+    /// built-in sound is used instead and the error is returned
+    pub fn set_sound(
+        &mut self,
+        sound_slot: SoundSlot,
+        sound_source: Option<&SoundSource>,
+    ) -> anyhow::Result<()> {
+        let channels = self.stream.config().channel_count();
+        let sample_rate = self.stream.config().sample_rate();
+
+        let (samples, result) = match sound_source
+            .map(|sound_source| sound_source.load(channels, sample_rate))
+            .transpose()
+        {
+            Ok(samples) => (samples, Ok(())),
+            Err(err) => (
+                None,
+                Err(err.context(format!(
+                    "Couldn't load the {} sound, using the built-in one instead",
+                    sound_slot
+                ))),
+            ),
+        };
+
+        match sound_slot {
+            SoundSlot::Click(accent) => {
+                let samples = match samples {
+                    Some(samples) => samples,
+                    None => decode_built_in_click(accent, channels, sample_rate)?,
+                };
+
+                // The mixer only goes away together with the stream, which we own
+                let _ = self.sender.send(MixerMessage::SetSound(accent, samples));
+            }
+            SoundSlot::Alarm => {
+                self.custom_alarm =
+                    samples.map(|samples| SamplesBuffer::new(channels, sample_rate, samples));
+            }
+        }
+
+        result
+    }
+
+    /// The alarm sound loaded from a file, if there is one
+    pub fn custom_alarm(&self) -> Option<SamplesBuffer> {
+        self.custom_alarm.clone()
+    }
+
+    /// Schedules a click for the beat that falls on `beat_timestamp`. It will be
+    /// heard `AUDIO_LATENCY` later
+    pub fn schedule_click(
+        &mut self,
+        beat_timestamp: Instant,
+        accent: MetronomeBeatAccent,
+        pan: f32,
+        gain: f32,
+    ) {
+        if let Some(mixer_position) = *self.mixer_position.lock().unwrap() {
+            self.frame_clock.correct(mixer_position);
+        }
+        let frame = self.frame_clock.frame_at(beat_timestamp + AUDIO_LATENCY);
+
+        // The mixer only goes away together with the stream, which we own
+        let _ = self.sender.send(MixerMessage::Click(ScheduledClick {
+            frame,
+            accent,
+            pan,
+            gain,
+        }));
+    }
+
+    pub fn mixer(&self) -> &Mixer {
+        self.stream.mixer()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metronome::data::beat::accent::MetronomeBeatAccent;
+    use crate::metronome::sound::{FrameClock, MixerPosition, SoundSlot, decode_sound_file};
+    use rodio::buffer::SamplesBuffer;
+    use std::{
+        env,
+        path::Path,
+        str::FromStr,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn frame_clock_follows_a_drifting_sound_card() {
+        let started_at = Instant::now();
+        let mut frame_clock = FrameClock::new(48000, started_at);
+
+        // A sound card 100 ppm fast, whose mixer fills 10ms buffers at once, so each
+        // position is seen up to a buffer late
+        let frame_at = |secs: f64| (secs * 48000.0 * 1.0001) as u64;
+        for buffer in 0..60 * 60 * 100 {
+            let secs = buffer as f64 / 100.0;
+            let lateness = (buffer % 7) as f64 / 700.0;
+            frame_clock.correct(MixerPosition {
+                frame: frame_at(secs),
+                at: started_at + Duration::from_secs_f64(secs + lateness),
+            });
+        }
+
+        // After an hour, the system clock alone would be off by 17280 frames
+        let hour = started_at + Duration::from_secs(60 * 60);
+        let error = frame_clock.frame_at(hour) as i64 - frame_at(3600.0) as i64;
+        assert!(error.abs() < 480, "{} frames off", error);
+    }
+
+    #[test]
+    fn sound_file_is_decoded_into_output_format() {
+        let path = env::temp_dir().join("metronome-test-sound.wav");
+        let mut source = SamplesBuffer::new(1, 1000, vec![0.5; 100]);
+        rodio::output_to_wav(&mut source, &path).unwrap();
+
+        let samples = decode_sound_file(&path, 2, 1000).unwrap();
+        assert_eq!(samples.len(), 200);
+    }
 
-    let sink = rodio::play(
-        stream_handler.mixer(),
-        Cursor::new(METRONOME_SOUNDS[metronome_beat_accent as usize]),
-    )?;
+    #[test]
+    fn invalid_sound_file() {
+        assert!(decode_sound_file(Path::new("does-not-exist.wav"), 2, 48000).is_err());
+        assert!(decode_sound_file(Path::new("Cargo.toml"), 2, 48000).is_err());
+    }
 
-    Ok((stream_handler, sink))
+    #[test]
+    fn sound_slot_from_str() {
+        assert_eq!(
+            SoundSlot::from_str("poly").unwrap(),
+            SoundSlot::Click(MetronomeBeatAccent::Polyrhythm)
+        );
+        assert_eq!(SoundSlot::Alarm.to_string(), "alarm");
+        assert!(SoundSlot::from_str("bell").is_err());
+    }
 }
@@ -0,0 +1,198 @@
+use anyhow::anyhow;
+use rodio::{ChannelCount, SampleRate};
+use std::{f32::consts::TAU, fmt, str::FromStr, time::Duration};
+
+use crate::metronome::data::beat::accent::MetronomeBeatAccent;
+
+/// How loud a synthesized click starts, before it decays
+const SYNTH_AMPLITUDE: f32 = 0.8;
+/// Square waves sound a lot louder than sines at the same amplitude
+const SQUARE_AMPLITUDE: f32 = 0.4;
+/// How long a click takes to fade in, so it doesn't pop
+const ATTACK: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Sine,
+    Square,
+    Noise,
+}
+
+impl FromStr for Waveform {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sine" => Ok(Self::Sine),
+            "square" => Ok(Self::Square),
+            "noise" => Ok(Self::Noise),
+            _ => Err(anyhow!(
+                "Invalid waveform `{}`! Must be sine, square or noise",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let string = match self {
+            Self::Sine => "sine",
+            Self::Square => "square",
+            Self::Noise => "noise",
+        };
+
+        write!(f, "{}", string)
+    }
+}
+
+/// A click made from a short burst of a waveform that decays exponentially
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SynthClick {
+    pub waveform: Waveform,
+    /// The pitch in Hz, which noise ignores
+    pub pitch: f32,
+    /// How long it takes the click to fade to about a third of its volume
+    pub decay: Duration,
+    pub length: Duration,
+}
+
+impl SynthClick {
+    /// The click used for each accent when no other settings are given. Higher accents
+    /// are higher pitched and ring a bit longer
+    pub fn default_for(accent: MetronomeBeatAccent) -> Self {
+        let (waveform, pitch, decay_ms, length_ms) = match accent {
+            MetronomeBeatAccent::Accented => (Waveform::Sine, 1760.0, 25, 80),
+            MetronomeBeatAccent::Beat => (Waveform::Sine, 1320.0, 20, 60),
+            MetronomeBeatAccent::Subdivision => (Waveform::Sine, 880.0, 15, 40),
+            MetronomeBeatAccent::Polyrhythm => (Waveform::Square, 990.0, 15, 50),
+        };
+
+        Self {
+            waveform,
+            pitch,
+            decay: Duration::from_millis(decay_ms),
+            length: Duration::from_millis(length_ms),
+        }
+    }
+
+    pub fn synthesize(&self, channels: ChannelCount, sample_rate: SampleRate) -> Vec<f32> {
+        let num_frames = (self.length.as_secs_f32() * sample_rate as f32).round() as usize;
+        let decay_secs = self.decay.as_secs_f32().max(f32::EPSILON);
+        // A small xorshift generator is plenty for noise, and keeps renders the same
+        // every time
+        let mut noise_state: u32 = 0x9E37_79B9;
+
+        let mut samples = Vec::with_capacity(num_frames * channels as usize);
+
+        for frame in 0..num_frames {
+            let t = frame as f32 / sample_rate as f32;
+
+            let wave = match self.waveform {
+                Waveform::Sine => (TAU * self.pitch * t).sin() * SYNTH_AMPLITUDE,
+                Waveform::Square => (TAU * self.pitch * t).sin().signum() * SQUARE_AMPLITUDE,
+                Waveform::Noise => {
+                    noise_state ^= noise_state << 13;
+                    noise_state ^= noise_state >> 17;
+                    noise_state ^= noise_state << 5;
+                    (noise_state as f32 / u32::MAX as f32 * 2.0 - 1.0) * SYNTH_AMPLITUDE
+                }
+            };
+
+            let attack = (t / ATTACK.as_secs_f32()).min(1.0);
+            let sample = wave * attack * (-t / decay_secs).exp();
+
+            samples.extend((0..channels).map(|_| sample));
+        }
+
+        samples
+    }
+}
+
+impl FromStr for SynthClick {
+    type Err = anyhow::Error;
+
+    /// Parses a click with the format of `<WAVEFORM> [PITCH] [DECAY] [LENGTH]`, where the
+    /// pitch is in Hz and the decay and length are in milliseconds
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let args: Vec<&str> = s.split_whitespace().collect();
+
+        if !(1..=4).contains(&args.len()) {
+            return Err(anyhow!(
+                "Invalid synth click! Example synth click: `sine 1500 20 60` is a 1500 Hz \
+                sine that decays over 20ms and lasts 60ms"
+            ));
+        }
+
+        let waveform: Waveform = args[0].parse()?;
+        let mut synth_click = Self {
+            waveform,
+            ..Self::default_for(MetronomeBeatAccent::Beat)
+        };
+
+        if let Some(pitch) = args.get(1) {
+            synth_click.pitch = pitch.parse()?;
+        }
+        if let Some(decay_ms) = args.get(2) {
+            synth_click.decay = Duration::from_millis(decay_ms.parse()?);
+        }
+        if let Some(length_ms) = args.get(3) {
+            synth_click.length = Duration::from_millis(length_ms.parse()?);
+        }
+
+        if !(20.0..=20000.0).contains(&synth_click.pitch) {
+            return Err(anyhow!("Pitch must be between 20-20000 Hz!"));
+        }
+        if synth_click.length.is_zero() || synth_click.length > Duration::from_secs(1) {
+            return Err(anyhow!("Length must be between 1-1000ms!"));
+        }
+
+        Ok(synth_click)
+    }
+}
+
+impl fmt::Display for SynthClick {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.waveform,
+            self.pitch,
+            self.decay.as_millis(),
+            self.length.as_millis()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metronome::sound::synth::{SynthClick, Waveform};
+    use std::{str::FromStr, time::Duration};
+
+    #[test]
+    fn synth_click_from_str() {
+        let synth_click = SynthClick::from_str("square 2000 10 30").unwrap();
+
+        assert_eq!(synth_click.waveform, Waveform::Square);
+        assert_eq!(synth_click.pitch, 2000.0);
+        assert_eq!(synth_click.decay, Duration::from_millis(10));
+        assert_eq!(synth_click.to_string(), "square 2000 10 30");
+
+        assert!(SynthClick::from_str("saw").is_err());
+        assert!(SynthClick::from_str("sine 5").is_err());
+        assert!(SynthClick::from_str("sine 1000 10 0").is_err());
+    }
+
+    #[test]
+    fn synth_click_decays() {
+        let samples = SynthClick::from_str("noise 1000 5 50")
+            .unwrap()
+            .synthesize(2, 1000);
+
+        assert_eq!(samples.len(), 100);
+        assert_eq!(samples[20], samples[21]);
+
+        let peak = |samples: &[f32]| samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(peak(&samples[80..]) < peak(&samples[..20]) / 10.0);
+    }
+}
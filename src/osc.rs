@@ -0,0 +1,393 @@
+use anyhow::anyhow;
+use std::{
+    io::{self, Write},
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use metronome::{BeatInfo, UserInput};
+
+use crate::ui::screen;
+
+/// Every address the metronome answers to starts with this
+const OSC_ADDRESS_PREFIX: &str = "/metronome/";
+const OSC_BEAT_ADDRESS: &str = "/metronome/beat";
+/// Large enough for any message a controller sends
+const OSC_MAX_PACKET_SIZE: usize = 4096;
+/// How long a peer keeps getting beats after its last message
+const PEER_TIMEOUT: Duration = Duration::from_secs(60);
+/// The most peers that get beats at once. The one heard from longest ago makes way
+/// for a new one, since some controllers send every message from a new port
+const PEERS_MAX: usize = 16;
+
+/// An argument of an OSC message
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+/// Reads the OSC string at the start of `bytes`, which is null terminated and padded
+/// to 4 bytes, returning it along with the bytes after it
+fn read_osc_string(bytes: &[u8]) -> anyhow::Result<(String, &[u8])> {
+    let end = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .ok_or_else(|| anyhow!("OSC string isn't terminated!"))?;
+    let padded_end = (end + 4) & !3;
+
+    let string = String::from_utf8(bytes[..end].to_vec())?;
+    Ok((string, bytes.get(padded_end..).unwrap_or_default()))
+}
+
+fn write_osc_string(bytes: &mut Vec<u8>, string: &str) {
+    bytes.extend_from_slice(string.as_bytes());
+    bytes.resize((bytes.len() + 4) & !3, 0);
+}
+
+fn read_4_bytes(bytes: &[u8]) -> anyhow::Result<([u8; 4], &[u8])> {
+    match bytes.split_first_chunk::<4>() {
+        Some((chunk, rest)) => Ok((*chunk, rest)),
+        None => Err(anyhow!("OSC argument is cut off!")),
+    }
+}
+
+impl OscMessage {
+    fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let (address, bytes) = read_osc_string(bytes)?;
+        if bytes.is_empty() {
+            return Ok(Self {
+                address,
+                args: Vec::new(),
+            });
+        }
+
+        let (type_tags, mut bytes) = read_osc_string(bytes)?;
+        let type_tags = type_tags
+            .strip_prefix(',')
+            .ok_or_else(|| anyhow!("OSC type tags must start with `,`!"))?;
+
+        let mut args = Vec::with_capacity(type_tags.len());
+
+        for type_tag in type_tags.chars() {
+            let arg = match type_tag {
+                'i' => {
+                    let (int_bytes, rest) = read_4_bytes(bytes)?;
+                    bytes = rest;
+                    OscArg::Int(i32::from_be_bytes(int_bytes))
+                }
+                'f' => {
+                    let (float_bytes, rest) = read_4_bytes(bytes)?;
+                    bytes = rest;
+                    OscArg::Float(f32::from_be_bytes(float_bytes))
+                }
+                's' => {
+                    let (string, rest) = read_osc_string(bytes)?;
+                    bytes = rest;
+                    OscArg::String(string)
+                }
+                _ => return Err(anyhow!("Unsupported OSC type tag `{}`!", type_tag)),
+            };
+            args.push(arg);
+        }
+
+        Ok(Self { address, args })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_osc_string(&mut bytes, &self.address);
+
+        let type_tags: String = std::iter::once(',')
+            .chain(self.args.iter().map(|arg| match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::String(_) => 's',
+            }))
+            .collect();
+        write_osc_string(&mut bytes, &type_tags);
+
+        for arg in &self.args {
+            match arg {
+                OscArg::Int(int) => bytes.extend_from_slice(&int.to_be_bytes()),
+                OscArg::Float(float) => bytes.extend_from_slice(&float.to_be_bytes()),
+                OscArg::String(string) => write_osc_string(&mut bytes, string),
+            }
+        }
+
+        bytes
+    }
+
+    /// The command this message stands for, written the same way as on the command
+    /// line. `/metronome/tempo 120` is `tempo 120`, and `/metronome/timer/stop` is
+    /// `timer stop`
+    pub fn to_command(&self) -> Option<String> {
+        let command = self
+            .address
+            .strip_prefix(OSC_ADDRESS_PREFIX)?
+            .replace('/', " ");
+
+        let args = self.args.iter().map(|arg| match arg {
+            OscArg::Int(int) => int.to_string(),
+            // Whole numbers are written without a decimal point, since most commands
+            // only take whole numbers
+            OscArg::Float(float) if float.fract() == 0.0 => (*float as i64).to_string(),
+            OscArg::Float(float) => float.to_string(),
+            OscArg::String(string) => string.clone(),
+        });
+
+        Some(
+            std::iter::once(command)
+                .chain(args)
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+    }
+}
+
+/// Decodes every message in a packet, unpacking bundles
+fn decode_packet(bytes: &[u8]) -> anyhow::Result<Vec<OscMessage>> {
+    let Some(mut elements) = bytes.strip_prefix(b"#bundle\0") else {
+        return Ok(vec![OscMessage::decode(bytes)?]);
+    };
+
+    // Bundles are played as soon as they arrive, so the time tag is skipped
+    elements = elements.get(8..).unwrap_or_default();
+    let mut messages = Vec::new();
+
+    while !elements.is_empty() {
+        let (size_bytes, rest) = read_4_bytes(elements)?;
+        let size = u32::from_be_bytes(size_bytes) as usize;
+        let element = rest
+            .get(..size)
+            .ok_or_else(|| anyhow!("OSC bundle element is cut off!"))?;
+
+        messages.extend(decode_packet(element)?);
+        elements = &rest[size..];
+    }
+
+    Ok(messages)
+}
+
+/// Where the beats go: the addresses given with `--osc-send`, and every peer that sent
+/// a message lately
+#[derive(Debug)]
+struct BeatReceivers {
+    fixed: Vec<SocketAddr>,
+    /// Each peer with when it last sent a message, from the longest ago
+    peers: Vec<(SocketAddr, Instant)>,
+}
+
+impl BeatReceivers {
+    fn heard_from(&mut self, peer_address: SocketAddr, now: Instant) {
+        if self.fixed.contains(&peer_address) {
+            return;
+        }
+
+        self.peers.retain(|(address, _)| *address != peer_address);
+        if self.peers.len() == PEERS_MAX {
+            self.peers.remove(0);
+        }
+        self.peers.push((peer_address, now));
+    }
+
+    /// Forgets the peers that have gone quiet, and returns everyone left
+    fn addresses(&mut self, now: Instant) -> Vec<SocketAddr> {
+        self.peers
+            .retain(|(_, last_heard)| now.saturating_duration_since(*last_heard) < PEER_TIMEOUT);
+
+        self.fixed
+            .iter()
+            .copied()
+            .chain(self.peers.iter().map(|(address, _)| *address))
+            .collect()
+    }
+}
+
+/// Listens for OSC messages on a local UDP port and sends them on as commands, and
+/// tells every address that has sent a message lately about each beat
+pub struct OscServer {
+    socket: UdpSocket,
+    beat_receivers: Arc<Mutex<BeatReceivers>>,
+}
+
+impl OscServer {
+    pub fn start(
+        port: u16,
+        beat_receivers: Vec<SocketAddr>,
+        sender: mpsc::Sender<UserInput>,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, port))?;
+        let beat_receivers = Arc::new(Mutex::new(BeatReceivers {
+            fixed: beat_receivers,
+            peers: Vec::new(),
+        }));
+
+        let receiving_socket = socket.try_clone()?;
+        let receiving_beat_receivers = Arc::clone(&beat_receivers);
+
+        thread::spawn(move || -> anyhow::Result<()> {
+            let mut packet = [0; OSC_MAX_PACKET_SIZE];
+
+            loop {
+                let (size, peer_address) = match receiving_socket.recv_from(&mut packet) {
+                    Ok(received) => received,
+                    // Windows reports a beat sent to a closed port on the next receive
+                    Err(err) if err.kind() == io::ErrorKind::ConnectionReset => continue,
+                    Err(err) => {
+                        writeln!(screen(), "Failed to receive an OSC message: {}", err)?;
+                        continue;
+                    }
+                };
+
+                // Packets that aren't valid OSC are ignored, like a typo on the command
+                // line would be
+                let Ok(messages) = decode_packet(&packet[..size]) else {
+                    continue;
+                };
+
+                receiving_beat_receivers
+                    .lock()
+                    .unwrap()
+                    .heard_from(peer_address, Instant::now());
+
+                for message in messages {
+                    if let Some(Ok(user_input)) =
+                        message.to_command().map(|command| command.parse())
+                    {
+                        sender.send(user_input)?;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            socket,
+            beat_receivers,
+        })
+    }
+
+    /// Sends `/metronome/beat` with the current beat and subdivided beat
+    pub fn send_beat(&self, beat_info: BeatInfo) {
+        let message = OscMessage {
+            address: OSC_BEAT_ADDRESS.to_string(),
+            args: vec![
+                OscArg::Int(beat_info.current_beat),
+                OscArg::Int(beat_info.subdivided_beat),
+            ],
+        };
+        let bytes = message.encode();
+
+        let beat_receivers = self
+            .beat_receivers
+            .lock()
+            .unwrap()
+            .addresses(Instant::now());
+        for beat_receiver in beat_receivers {
+            // A receiver that went away shouldn't stop the metronome
+            let _ = self.socket.send_to(&bytes, beat_receiver);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::osc::{BeatReceivers, OscArg, OscMessage, PEER_TIMEOUT, PEERS_MAX, decode_packet};
+    use std::{
+        net::SocketAddr,
+        time::{Duration, Instant},
+    };
+
+    fn message(address: &str, args: Vec<OscArg>) -> OscMessage {
+        OscMessage {
+            address: address.to_string(),
+            args,
+        }
+    }
+
+    #[test]
+    fn message_round_trip() {
+        let tempo = message(
+            "/metronome/tempo",
+            vec![
+                OscArg::Int(120),
+                OscArg::Float(0.5),
+                OscArg::String("abc".to_string()),
+            ],
+        );
+        let bytes = tempo.encode();
+
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(&bytes[..20], b"/metronome/tempo\0\0\0\0");
+        assert_eq!(decode_packet(&bytes).unwrap(), [tempo]);
+    }
+
+    #[test]
+    fn bundles_are_unpacked() {
+        let pause = message("/metronome/pause", Vec::new()).encode();
+        let time = message("/metronome/time", vec![OscArg::String("7/8".to_string())]).encode();
+
+        let mut bundle = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        for element in [&pause, &time] {
+            bundle.extend_from_slice(&(element.len() as u32).to_be_bytes());
+            bundle.extend_from_slice(element);
+        }
+
+        let commands: Vec<String> = decode_packet(&bundle)
+            .unwrap()
+            .iter()
+            .filter_map(OscMessage::to_command)
+            .collect();
+        assert_eq!(commands, ["pause", "time 7/8"]);
+    }
+
+    #[test]
+    fn messages_become_commands() {
+        let command = |address: &str, args: Vec<OscArg>| message(address, args).to_command();
+
+        assert_eq!(
+            command("/metronome/subdivision", vec![OscArg::Float(3.0)]).as_deref(),
+            Some("subdivision 3")
+        );
+        assert_eq!(
+            command("/metronome/timer/stop", Vec::new()).as_deref(),
+            Some("timer stop")
+        );
+        assert_eq!(command("/lights/tempo", vec![OscArg::Int(120)]), None);
+        assert!(decode_packet(b"/metronome/tempo\0\0\0\0,i\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn quiet_and_oldest_peers_stop_getting_beats() {
+        let address = |port: u16| SocketAddr::from(([127, 0, 0, 1], port));
+        let start = Instant::now();
+        let mut beat_receivers = BeatReceivers {
+            fixed: vec![address(9000)],
+            peers: Vec::new(),
+        };
+
+        beat_receivers.heard_from(address(9000), start);
+        for port in 0..=PEERS_MAX as u16 {
+            beat_receivers.heard_from(address(50000 + port), start);
+        }
+        // Hearing from a peer again keeps it from being the oldest
+        beat_receivers.heard_from(address(50001), start + Duration::from_secs(30));
+
+        let addresses = beat_receivers.addresses(start);
+        assert_eq!(addresses.len(), 1 + PEERS_MAX);
+        assert_eq!(addresses[0], address(9000));
+        assert!(!addresses.contains(&address(50000)));
+
+        let addresses = beat_receivers.addresses(start + PEER_TIMEOUT);
+        assert_eq!(addresses, [address(9000), address(50001)]);
+    }
+}
@@ -1,33 +1,141 @@
-use std::time::{Duration, Instant};
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
 
-const DEFAULT_CAPACITY: usize = 8;
+use crate::metronome::data::TEMPO_MIN;
+
+/// How many of the latest taps the tempo is measured from
+pub const TAP_WINDOW: usize = 8;
+
+/// How far an interval can be from the median interval before it's ignored, as a
+/// fraction of the median
+const OUTLIER_TOLERANCE: f64 = 0.25;
+
+/// Taps further apart than the slowest possible beat start a new measurement
+const TAP_TIMEOUT: Duration = Duration::from_secs(60 / TEMPO_MIN as u64);
+
+/// The tempo measured from the taps, along with how steady they were
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoEstimate {
+    pub tempo: f64,
+    /// The standard deviation of the intervals between taps, as a percentage of the
+    /// average interval
+    pub deviation: f64,
+    /// How many intervals were ignored for being too far from the rest
+    pub outliers: usize,
+}
+
+impl fmt::Display for TempoEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} BPM (±{:.1}%", self.tempo, self.deviation)?;
+
+        match self.outliers {
+            0 => {}
+            1 => write!(f, ", 1 tap ignored")?,
+            outliers => write!(f, ", {} taps ignored", outliers)?,
+        }
+
+        write!(f, ")")
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct TempoMeasurer {
-    timestamps: Vec<Instant>,
+    timestamps: VecDeque<Instant>,
+    taps_per_beat: u32,
+}
+
+impl Default for TempoMeasurer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TempoMeasurer {
     pub fn new() -> Self {
         Self {
-            timestamps: Vec::with_capacity(DEFAULT_CAPACITY),
+            timestamps: VecDeque::with_capacity(TAP_WINDOW),
+            taps_per_beat: 1,
         }
     }
 
-    pub fn tap(&mut self) {
-        self.timestamps.push(Instant::now());
+    /// Sets how many taps make up a beat, for tapping subdivisions instead of beats
+    pub fn set_taps_per_beat(&mut self, taps_per_beat: u32) {
+        self.taps_per_beat = taps_per_beat.max(1);
+    }
+
+    pub fn taps_per_beat(&self) -> u32 {
+        self.taps_per_beat
+    }
+
+    pub fn tap(&mut self, timestamp: Instant) {
+        if let Some(&last_timestamp) = self.timestamps.back()
+            && timestamp.saturating_duration_since(last_timestamp) > TAP_TIMEOUT
+        {
+            self.timestamps.clear();
+        }
+
+        if self.timestamps.len() == TAP_WINDOW {
+            self.timestamps.pop_front();
+        }
+        self.timestamps.push_back(timestamp);
     }
 
-    pub fn calculate_tempo(&self) -> i32 {
-        let total_duration = self
+    /// Measures the tempo from the intervals between the taps in the window, ignoring
+    /// the ones that are too far from the median. Needs at least 2 taps, and some
+    /// intervals close to the median
+    pub fn estimate(&self) -> Option<TempoEstimate> {
+        let intervals: Vec<f64> = self
             .timestamps
-            .windows(2)
-            .fold(Duration::ZERO, |acc, timestamps| {
-                acc + (timestamps[1] - timestamps[0])
-            });
+            .iter()
+            .zip(self.timestamps.iter().skip(1))
+            .map(|(previous, next)| (*next - *previous).as_secs_f64())
+            .collect();
+
+        if intervals.is_empty() {
+            return None;
+        }
+
+        let mut sorted_intervals = intervals.clone();
+        sorted_intervals.sort_by(f64::total_cmp);
+        let middle = sorted_intervals.len() / 2;
+        let median = if sorted_intervals.len().is_multiple_of(2) {
+            (sorted_intervals[middle - 1] + sorted_intervals[middle]) / 2.0
+        } else {
+            sorted_intervals[middle]
+        };
+
+        let kept_intervals: Vec<f64> = intervals
+            .iter()
+            .copied()
+            .filter(|interval| (interval - median).abs() <= median * OUTLIER_TOLERANCE)
+            .collect();
 
-        let secs_per_beat = total_duration.as_secs_f64() / (self.timestamps.len() - 1) as f64;
-        (1.0 / secs_per_beat * 60.0) as i32
+        // With an even number of intervals, the median can fall between two groups that
+        // are both too far from it, and then the taps are too uneven to measure
+        if kept_intervals.is_empty() {
+            return None;
+        }
+
+        let mean = kept_intervals.iter().sum::<f64>() / kept_intervals.len() as f64;
+        let variance = kept_intervals
+            .iter()
+            .map(|interval| (interval - mean).powi(2))
+            .sum::<f64>()
+            / kept_intervals.len() as f64;
+
+        Some(TempoEstimate {
+            tempo: 60.0 / mean / self.taps_per_beat as f64,
+            deviation: variance.sqrt() / mean * 100.0,
+            outliers: intervals.len() - kept_intervals.len(),
+        })
+    }
+
+    pub fn calculate_tempo(&self) -> Option<i32> {
+        self.estimate()
+            .map(|tempo_estimate| tempo_estimate.tempo.round() as i32)
     }
 
     pub fn num_tapped(&self) -> usize {
@@ -36,6 +144,83 @@ impl TempoMeasurer {
 
     pub fn clear(&mut self) {
         self.timestamps.clear();
-        self.timestamps.shrink_to(DEFAULT_CAPACITY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tempo_measurer::{TAP_WINDOW, TempoMeasurer};
+    use std::time::{Duration, Instant};
+
+    /// Taps once, then once after every interval, returning when the last tap was
+    fn tap_intervals(
+        tempo_measurer: &mut TempoMeasurer,
+        mut timestamp: Instant,
+        intervals_ms: &[u64],
+    ) -> Instant {
+        tempo_measurer.tap(timestamp);
+
+        for interval_ms in intervals_ms {
+            timestamp += Duration::from_millis(*interval_ms);
+            tempo_measurer.tap(timestamp);
+        }
+
+        timestamp
+    }
+
+    #[test]
+    fn late_tap_is_ignored() {
+        let mut tempo_measurer = TempoMeasurer::new();
+        tap_intervals(
+            &mut tempo_measurer,
+            Instant::now(),
+            &[500, 500, 900, 500, 500],
+        );
+
+        let tempo_estimate = tempo_measurer.estimate().unwrap();
+        assert_eq!(tempo_estimate.outliers, 1);
+        assert_eq!(tempo_measurer.calculate_tempo(), Some(120));
+        assert_eq!(
+            tempo_estimate.to_string(),
+            "120.0 BPM (±0.0%, 1 tap ignored)"
+        );
+    }
+
+    #[test]
+    fn uneven_taps_are_not_measured() {
+        let mut tempo_measurer = TempoMeasurer::new();
+        tap_intervals(&mut tempo_measurer, Instant::now(), &[500, 500, 1000, 1000]);
+
+        // The median of 750 ms is too far from every interval
+        assert_eq!(tempo_measurer.estimate(), None);
+        assert_eq!(tempo_measurer.calculate_tempo(), None);
+    }
+
+    #[test]
+    fn only_latest_taps_are_measured() {
+        let mut tempo_measurer = TempoMeasurer::new();
+        let timestamp = tap_intervals(&mut tempo_measurer, Instant::now(), &[1000; TAP_WINDOW]);
+        tap_intervals(
+            &mut tempo_measurer,
+            timestamp + Duration::from_millis(600),
+            &[600; TAP_WINDOW],
+        );
+
+        assert_eq!(tempo_measurer.num_tapped(), TAP_WINDOW);
+        assert_eq!(tempo_measurer.calculate_tempo(), Some(100));
+    }
+
+    #[test]
+    fn subdivided_taps_and_rounding() {
+        let mut tempo_measurer = TempoMeasurer::new();
+        tempo_measurer.set_taps_per_beat(2);
+        tap_intervals(&mut tempo_measurer, Instant::now(), &[333, 333, 333]);
+
+        // 90.09 BPM is rounded, not truncated
+        assert_eq!(tempo_measurer.calculate_tempo(), Some(90));
+
+        tempo_measurer.clear();
+        tap_intervals(&mut tempo_measurer, Instant::now(), &[]);
+        assert_eq!(tempo_measurer.calculate_tempo(), None);
     }
 }
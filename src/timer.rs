@@ -1,10 +1,12 @@
 use anyhow::anyhow;
-use rodio::{Decoder, Source};
+use rodio::{Decoder, Source, buffer::SamplesBuffer, mixer::Mixer};
 use std::fmt::Write;
 use std::io::Cursor;
 use std::str::FromStr;
 use std::time::{Duration, Instant};
 
+use crate::clock::{SharedClock, SystemClock};
+
 static ALARM_AUDIO_DATA: &[u8] = include_bytes!("./audio/timer-alarm.mp3");
 
 pub mod render_tracker;
@@ -16,31 +18,40 @@ fn create_infinite_playback(audio_data: &'static [u8]) -> anyhow::Result<impl So
     Ok(source_looped)
 }
 
-pub fn play_timer_alarm() -> anyhow::Result<(rodio::OutputStream, rodio::Sink)> {
-    let mut stream_handler = rodio::OutputStreamBuilder::open_default_stream()?;
-    stream_handler.log_on_drop(false);
-
-    let sink = rodio::Sink::connect_new(stream_handler.mixer());
-
-    let source = create_infinite_playback(ALARM_AUDIO_DATA)?;
+/// Plays the alarm until the returned sink is dropped. `custom_alarm` is played
+/// instead of the built-in alarm if there is one
+pub fn play_timer_alarm(
+    mixer: &Mixer,
+    custom_alarm: Option<SamplesBuffer>,
+    volume: f32,
+) -> anyhow::Result<rodio::Sink> {
+    let sink = rodio::Sink::connect_new(mixer);
+    sink.set_volume(volume);
+
+    match custom_alarm {
+        Some(custom_alarm) => sink.append(custom_alarm.repeat_infinite()),
+        None => sink.append(create_infinite_playback(ALARM_AUDIO_DATA)?),
+    }
 
-    sink.append(source);
     sink.play();
 
-    Ok((stream_handler, sink))
+    Ok(sink)
 }
 
 #[derive(Debug, Clone)]
 pub struct Timer {
     created_timestamp: Instant,
     duration: Duration,
+    clock: SharedClock,
 }
 
 impl Timer {
-    fn new(duration: Duration) -> Self {
+    /// A timer that starts counting down now, on `clock`
+    pub fn start(duration: Duration, clock: SharedClock) -> Self {
         Self {
-            created_timestamp: Instant::now(),
+            created_timestamp: clock.now(),
             duration,
+            clock,
         }
     }
 
@@ -73,58 +84,67 @@ impl Timer {
 
     pub fn time_remaining(&self) -> Duration {
         self.duration
-            .checked_sub(self.created_timestamp.elapsed())
+            .checked_sub(
+                self.clock
+                    .now()
+                    .saturating_duration_since(self.created_timestamp),
+            )
             .unwrap_or(Duration::ZERO)
     }
 }
 
-impl FromStr for Timer {
-    type Err = anyhow::Error;
+/// Parses a duration with the format of `HH:MM:SS`, where hours are optional
+pub fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    if s.is_empty() {
+        return Err(anyhow!("Timer string cannot be empty"));
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.is_empty() {
-            return Err(anyhow!("Timer string cannot be empty"));
-        }
+    let times = s.split(":").collect::<Vec<&str>>();
 
-        let times = s.split(":").collect::<Vec<&str>>();
+    if times.len() < 2 {
+        return Err(anyhow!("Must include minutes and seconds"));
+    }
 
-        if times.len() < 2 {
-            return Err(anyhow!("Must include minutes and seconds"));
-        }
+    let seconds: u64;
+    let minutes: u64;
+    let mut hours = 0u64;
 
-        let seconds: u64;
-        let minutes: u64;
-        let mut hours = 0u64;
-
-        match times.len() {
-            2 => {
-                minutes = times[0].parse()?;
-                seconds = times[1].parse()?;
-            }
-            3 => {
-                hours = times[0].parse()?;
-                minutes = times[1].parse()?;
-                seconds = times[2].parse()?;
-            }
-            _ => {
-                return Err(anyhow!("Invalid format!"));
-            }
+    match times.len() {
+        2 => {
+            minutes = times[0].parse()?;
+            seconds = times[1].parse()?;
         }
-
-        if seconds >= 60 || minutes >= 60 {
-            return Err(anyhow!("Invalid time!"));
+        3 => {
+            hours = times[0].parse()?;
+            minutes = times[1].parse()?;
+            seconds = times[2].parse()?;
         }
-        if hours > 100 {
-            return Err(anyhow!("Hours must be less than or equal to 100"));
+        _ => {
+            return Err(anyhow!("Invalid format!"));
         }
+    }
 
-        let duration = Duration::from_secs(seconds + minutes * 60 + hours * 3600);
+    if seconds >= 60 || minutes >= 60 {
+        return Err(anyhow!("Invalid time!"));
+    }
+    if hours > 100 {
+        return Err(anyhow!("Hours must be less than or equal to 100"));
+    }
 
-        if duration.is_zero() {
-            return Err(anyhow!("The timer must not be zero seconds long!"));
-        }
+    let duration = Duration::from_secs(seconds + minutes * 60 + hours * 3600);
+
+    if duration.is_zero() {
+        return Err(anyhow!("The timer must not be zero seconds long!"));
+    }
 
-        Ok(Self::new(duration))
+    Ok(duration)
+}
+
+impl FromStr for Timer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::start(parse_duration(s)?, SystemClock::shared()))
     }
 }
 
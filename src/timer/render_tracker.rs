@@ -1,17 +1,20 @@
+use crate::clock::SharedClock;
 use crate::metronome::data::MetronomeData;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 
 pub struct TimerRenderTracker {
     metronome_data: Arc<RwLock<MetronomeData>>,
+    clock: SharedClock,
     last_rerender_timestamp: Instant,
 }
 
 impl TimerRenderTracker {
-    pub fn new(metronome_data: Arc<RwLock<MetronomeData>>) -> Self {
+    pub fn new(metronome_data: Arc<RwLock<MetronomeData>>, clock: SharedClock) -> Self {
         Self {
             metronome_data,
-            last_rerender_timestamp: Instant::now(),
+            last_rerender_timestamp: clock.now(),
+            clock,
         }
     }
 
@@ -20,10 +23,23 @@ impl TimerRenderTracker {
             return false;
         }
 
-        self.last_rerender_timestamp.elapsed() >= Duration::from_secs(1)
+        self.clock
+            .now()
+            .saturating_duration_since(self.last_rerender_timestamp)
+            >= Duration::from_secs(1)
+    }
+
+    /// When the timer should be rendered next, or `None` if there's no timer
+    pub fn next_render_timestamp(&self) -> Option<Instant> {
+        self.metronome_data
+            .read()
+            .unwrap()
+            .timer
+            .as_ref()
+            .map(|_| self.last_rerender_timestamp + Duration::from_secs(1))
     }
 
     pub fn just_rendered(&mut self) {
-        self.last_rerender_timestamp = Instant::now();
+        self.last_rerender_timestamp = self.clock.now();
     }
 }
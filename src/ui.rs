@@ -6,17 +6,47 @@ use std::{
     fmt::{self, Write as FmtWrite},
     io::{self, Write},
     mem,
-    sync::{Arc, RwLock},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 
-use crate::metronome::data::{
-    MetronomeData,
-    beat::{BeatInfo, accent::get_metronome_beat_accent},
+use metronome::{
+    TAPS_NEEDED,
+    metronome::data::{
+        MetronomeData,
+        beat::{
+            BeatInfo,
+            accent::{MetronomeBeatAccent, get_metronome_beat_accent},
+        },
+        polyrhythm::PolyrhythmScope,
+    },
 };
 
 const SCREEN_TEXT_CAPACITY: usize = 256;
 
-const BEAT_CHAR: [char; 3] = ['X', 'x', '.'];
+/// Whether the screen is drawn on stderr, because stdout carries the event log
+static IS_SCREEN_ON_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Draws the screen and any messages on stderr from now on, leaving stdout to the
+/// event log
+pub fn move_screen_to_stderr() {
+    IS_SCREEN_ON_STDERR.store(true, Ordering::Relaxed);
+}
+
+/// Where the screen and messages are written, stdout unless it was moved to stderr
+pub fn screen() -> Box<dyn Write> {
+    if IS_SCREEN_ON_STDERR.load(Ordering::Relaxed) {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    }
+}
+
+/// The widest the polyrhythm grid gets. Polyrhythms that need more cells to line up
+/// both layers are only described, not drawn
+const POLYRHYTHM_GRID_MAX_CELLS: i32 = 48;
 
 // fn get_beat_to_print(beat_index: i32, is_eighths_time_signature: bool) -> char {
 //     match is_eighths_time_signature {
@@ -42,6 +72,8 @@ const BEAT_CHAR: [char; 3] = ['X', 'x', '.'];
 pub struct Ui {
     screen_text: String,
     metronome_data: Arc<RwLock<MetronomeData>>,
+    /// Whether nothing is drawn, like when running as a daemon
+    is_headless: bool,
 }
 
 impl Ui {
@@ -49,31 +81,109 @@ impl Ui {
         Self {
             metronome_data,
             screen_text: String::with_capacity(SCREEN_TEXT_CAPACITY),
+            is_headless: false,
+        }
+    }
+
+    /// A Ui that never draws anything, but can still describe the settings
+    pub fn headless(metronome_data: Arc<RwLock<MetronomeData>>) -> Self {
+        Self {
+            is_headless: true,
+            ..Self::new(metronome_data)
         }
     }
 
     pub fn render(&mut self) -> io::Result<()> {
-        io::stdout()
+        if self.is_headless {
+            return Ok(());
+        }
+
+        let mut screen = screen();
+        screen
             .queue(cursor::SavePosition)?
             .queue(cursor::MoveTo(0, 0))?;
 
         self.write_info_text().unwrap();
+        self.write_tempo_ramp_text().unwrap();
+        self.write_gap_click_text().unwrap();
+        self.write_dropout_text().unwrap();
+        self.write_program_text().unwrap();
         self.write_metronome_beat_text().unwrap();
+        self.write_polyrhythm_text().unwrap();
         self.write_timer_text().unwrap();
+        self.write_tap_text().unwrap();
+        self.write_tap_along_text().unwrap();
 
         let screen_text = mem::replace(
             &mut self.screen_text,
             String::with_capacity(SCREEN_TEXT_CAPACITY),
         );
 
-        io::stdout()
-            .queue(Print(screen_text))?
+        // Keys can be read in raw mode, where a new line stays in the same column
+        screen
+            .queue(Print(screen_text.replace('\n', "\r\n")))?
             .queue(cursor::RestorePosition)?
             .flush()?;
 
         Ok(())
     }
 
+    /// Prints text below the screen, like the answer to a command. Keys can be read in
+    /// raw mode, where a new line stays in the same column, so lines end with `\r\n`
+    pub fn print_lines(&self, text: &str) -> io::Result<()> {
+        let mut screen = screen();
+
+        for line in text.lines() {
+            write!(screen, "{}\r\n", line)?;
+        }
+
+        screen.flush()
+    }
+
+    /// Says below the screen which mode the metronome is in, after a command
+    pub fn print_mode_text(&self) -> io::Result<()> {
+        if self.is_headless {
+            return Ok(());
+        }
+
+        let mut mode_text = String::new();
+        {
+            let metronome_data = self.metronome_data.read().unwrap();
+            if metronome_data.is_paused {
+                mode_text.push_str("PAUSED!\n");
+            }
+            if metronome_data.tap_mode {
+                mode_text.push_str("TAP MODE. Press any key for each beat. Press `q` to exit.\n");
+            }
+            if metronome_data.tap_along.is_some() {
+                mode_text
+                    .push_str("TAP ALONG. Press any key with each click. Press `q` to exit.\n");
+            }
+        }
+
+        self.print_lines(&mode_text)
+    }
+
+    /// The settings as plain text, without the beats, for `metronome ctl`
+    pub fn status_text(&mut self) -> String {
+        self.write_info_text().unwrap();
+        self.write_tempo_ramp_text().unwrap();
+        self.write_gap_click_text().unwrap();
+        self.write_dropout_text().unwrap();
+        self.write_program_text().unwrap();
+        self.write_polyrhythm_text().unwrap();
+        self.write_timer_text().unwrap();
+
+        if self.metronome_data.read().unwrap().is_paused {
+            writeln!(self.screen_text, "PAUSED").unwrap();
+        }
+
+        mem::replace(
+            &mut self.screen_text,
+            String::with_capacity(SCREEN_TEXT_CAPACITY),
+        )
+    }
+
     fn write_info_text(&mut self) -> fmt::Result {
         let metronome_data = self.metronome_data.read().unwrap();
 
@@ -99,11 +209,116 @@ impl Ui {
             )?;
         }
 
+        write!(self.screen_text, "\t\tVolume = {}", metronome_data.volume)?;
+
+        writeln!(self.screen_text)?;
+
+        Ok(())
+    }
+
+    fn write_tempo_ramp_text(&mut self) -> fmt::Result {
+        if let Some(ref tempo_ramp) = self.metronome_data.read().unwrap().tempo_ramp {
+            writeln!(self.screen_text, "RAMP: {}", tempo_ramp)?;
+        }
+
+        Ok(())
+    }
+
+    /// Shows the tempo measured so far in tap mode, and how steady the taps are
+    fn write_tap_text(&mut self) -> fmt::Result {
+        let data = self.metronome_data.read().unwrap();
+        if !data.tap_mode {
+            return Ok(());
+        }
+
+        let tempo_measurer = &data.tempo_measurer;
+        write!(self.screen_text, "TAP: ")?;
+
+        match tempo_measurer.estimate() {
+            Some(tempo_estimate) => write!(self.screen_text, "{}", tempo_estimate)?,
+            None => write!(self.screen_text, "-")?,
+        }
+
+        // The tempo only changes once enough taps are in
+        let num_tapped = tempo_measurer.num_tapped();
+        if num_tapped < TAPS_NEEDED {
+            write!(self.screen_text, "\t\t{}/{} taps", num_tapped, TAPS_NEEDED)?;
+        } else {
+            write!(self.screen_text, "\t\t{} taps", num_tapped)?;
+        }
+
+        if tempo_measurer.taps_per_beat() > 1 {
+            write!(
+                self.screen_text,
+                ", {} per {}",
+                tempo_measurer.taps_per_beat(),
+                data.tempo_type()
+            )?;
+        }
+
+        writeln!(self.screen_text)?;
+
+        Ok(())
+    }
+
+    /// Shows how early or late the latest taps were, and how they add up
+    fn write_tap_along_text(&mut self) -> fmt::Result {
+        let data = self.metronome_data.read().unwrap();
+        let Some(ref tap_along) = data.tap_along else {
+            return Ok(());
+        };
+
+        write!(self.screen_text, "TAP ALONG:")?;
+
+        let Some((mean, std_dev)) = tap_along.mean_and_std_dev() else {
+            return writeln!(self.screen_text, " -");
+        };
+
+        for offset_ms in tap_along.latest_offsets_ms() {
+            write!(self.screen_text, " {:+.0}", offset_ms)?;
+        }
+
+        write!(
+            self.screen_text,
+            " ms\t\tAverage = {:+.1} ms ± {:.1} ms over {} taps",
+            mean,
+            std_dev,
+            tap_along.num_tapped()
+        )?;
+
+        if let Some(verdict) = tap_along.last_bar_verdict() {
+            write!(self.screen_text, "\t\tLast bar: {}", verdict)?;
+        }
+
         writeln!(self.screen_text)?;
 
         Ok(())
     }
 
+    fn write_gap_click_text(&mut self) -> fmt::Result {
+        if let Some(gap_click) = self.metronome_data.read().unwrap().gap_click {
+            writeln!(self.screen_text, "GAP: {}", gap_click)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_dropout_text(&mut self) -> fmt::Result {
+        if let Some(dropout) = self.metronome_data.read().unwrap().dropout {
+            writeln!(self.screen_text, "DROPOUT: {}", dropout)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_program_text(&mut self) -> fmt::Result {
+        if let Some(ref program) = self.metronome_data.read().unwrap().program {
+            writeln!(self.screen_text, "PROGRAM: {}", program)?;
+        }
+
+        Ok(())
+    }
+
     fn write_metronome_beat_text(&mut self) -> fmt::Result {
         write!(self.screen_text, "[    ")?;
 
@@ -114,17 +329,83 @@ impl Ui {
             // let beat_to_print = get_beat_to_print(i, data.time_signature_is_eighths());
             let current_beat_accent =
                 get_metronome_beat_accent(data.beat_accents(), BeatInfo::from((i, 0)));
-            let beat_to_print = BEAT_CHAR[current_beat_accent as usize];
+            let beat_to_print = current_beat_accent.to_char();
 
-            if i == data.beat_info.current_beat {
-                write!(self.screen_text, "{}", beat_to_print.italic().blue())?;
-            } else {
-                write!(self.screen_text, "{}", beat_to_print)?;
+            // Beats of bars silenced by the gap click are dimmed
+            match (i == data.beat_info.current_beat, data.is_muted()) {
+                (true, false) => write!(self.screen_text, "{}", beat_to_print.italic().blue())?,
+                (true, true) => write!(self.screen_text, "{}", beat_to_print.italic().dim())?,
+                (false, false) => write!(self.screen_text, "{}", beat_to_print)?,
+                (false, true) => write!(self.screen_text, "{}", beat_to_print.dim())?,
             }
 
             write!(self.screen_text, " ")?;
         }
 
+        write!(self.screen_text, "   ]")?;
+
+        if data.is_muted() {
+            write!(self.screen_text, "  (silent)")?;
+        }
+
+        writeln!(self.screen_text)?;
+
+        Ok(())
+    }
+
+    /// Draws both layers of the polyrhythm over one span, lined up on a shared grid
+    fn write_polyrhythm_text(&mut self) -> fmt::Result {
+        let data = self.metronome_data.read().unwrap();
+        let Some(polyrhythm) = data.polyrhythm else {
+            return Ok(());
+        };
+
+        let subdivision = data.subdivision();
+        let main_pulses = polyrhythm.main_pulses(data.time_signature().0, subdivision);
+
+        writeln!(
+            self.screen_text,
+            "POLY: {}:{} ({}, {} {}%)",
+            polyrhythm.pulses,
+            main_pulses,
+            polyrhythm,
+            MetronomeBeatAccent::Polyrhythm.to_char(),
+            data.volume.polyrhythm
+        )?;
+
+        let grid_cells = polyrhythm.grid_cells(main_pulses);
+        if grid_cells > POLYRHYTHM_GRID_MAX_CELLS {
+            return Ok(());
+        }
+
+        write!(self.screen_text, "[    ")?;
+        for cell in 0..grid_cells {
+            let cells_per_pulse = grid_cells / main_pulses;
+
+            let cell_to_print = if cell % cells_per_pulse == 0 {
+                let pulse = cell / cells_per_pulse;
+                let beat_info = match polyrhythm.scope {
+                    PolyrhythmScope::Beat => (data.beat_info.current_beat, pulse),
+                    PolyrhythmScope::Bar => (pulse / subdivision, pulse % subdivision),
+                };
+
+                get_metronome_beat_accent(data.beat_accents(), BeatInfo::from(beat_info)).to_char()
+            } else {
+                ' '
+            };
+            write!(self.screen_text, "{} ", cell_to_print)?;
+        }
+        writeln!(self.screen_text, "   ]")?;
+
+        write!(self.screen_text, "[    ")?;
+        for cell in 0..grid_cells {
+            let cell_to_print = if cell % (grid_cells / polyrhythm.pulses) == 0 {
+                MetronomeBeatAccent::Polyrhythm.to_char()
+            } else {
+                ' '
+            };
+            write!(self.screen_text, "{} ", cell_to_print)?;
+        }
         writeln!(self.screen_text, "   ]")?;
 
         Ok(())
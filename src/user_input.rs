@@ -1,23 +1,45 @@
 use UserInput::*;
-use std::str::FromStr;
+use std::{str::FromStr, sync::mpsc, time::Instant};
 
 #[derive(Debug, Clone)]
 pub enum UserInput {
     Pause,
     Resume,
+    TogglePause,
     Quit,
     Help,
     Clear,
-    Tap,
+    /// Starts tap mode, with how many taps make up a beat
+    Tap(String),
+    /// Leaves tap mode and plays at the tapped tempo
+    StopTap,
+    /// A tap in tap mode, with when it happened
+    TapBeat(Instant),
+    StartTapAlong,
+    StopTapAlong,
     SetTempo(String),
-    SetTempoDirect(i32),
+    NudgeTempo(i32),
     SetTimeSignature(String),
     SetTempoType(String),
     SetSubdivision(String),
     SetSubdivisionSetting(String),
+    SetAccents(String),
+    SetTempoRamp(String),
+    SetPolyrhythm(String),
+    SetGapClick(String),
+    SetDropout(String),
+    SetVolume(String),
+    SetSound(String, String),
+    LoadProgram(String),
+    StopProgram,
+    GotoSection(String),
+    LoopSection(String),
     Unknown(String),
     SetTimer(String),
     StopTimer,
+    /// Asks for the settings as text once every earlier input is handled, for
+    /// `metronome ctl`
+    SendStatus(mpsc::Sender<String>),
 }
 
 impl FromStr for UserInput {
@@ -26,6 +48,8 @@ impl FromStr for UserInput {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let lowercase = s.to_lowercase();
         let args: Vec<&str> = lowercase.trim().split(" ").collect();
+        // Some arguments are case sensitive, so they're taken from the original string
+        let original_args: Vec<&str> = s.trim().split(" ").collect();
 
         if args.is_empty() {
             return Ok(Clear);
@@ -36,15 +60,55 @@ impl FromStr for UserInput {
         Ok(match args[0] {
             "pause" | "p" => Pause,
             "resume" | "r" => Resume,
+            "toggle" => TogglePause,
             "quit" | "q" => Quit,
             "help" | "h" => Help,
             "clear" | "c" | "" => Clear,
-            "tempo" | "t" => SetTempo(get_nth_arg(1)),
+            "tempo" | "t" => {
+                let tempo_str = get_nth_arg(1);
+
+                // A signed tempo like `+5` changes the tempo instead of setting it
+                match tempo_str.parse::<i32>() {
+                    Ok(change) if tempo_str.starts_with(['+', '-']) => NudgeTempo(change),
+                    _ => SetTempo(tempo_str),
+                }
+            }
             "time" => SetTimeSignature(get_nth_arg(1)),
             "tempo-type" | "tt" => SetTempoType(get_nth_arg(1)),
             "subdivision" | "s" => SetSubdivision(get_nth_arg(1)),
             "subdivision-setting" | "ss" => SetSubdivisionSetting(get_nth_arg(1)),
-            "tap" => Tap,
+            "accent" | "a" => SetAccents(
+                original_args
+                    .get(1)
+                    .copied()
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            "ramp" => SetTempoRamp(args[1..].join(" ")),
+            "poly" => SetPolyrhythm(args[1..].join(" ")),
+            "gap" => SetGapClick(args[1..].join(" ")),
+            "dropout" => SetDropout(args[1..].join(" ")),
+            "volume" | "v" => SetVolume(args[1..].join(" ")),
+            // File paths are case sensitive, so they're taken from the original string
+            "sound" => SetSound(
+                get_nth_arg(1),
+                original_args.get(2..).unwrap_or_default().join(" "),
+            ),
+            "program" => match get_nth_arg(1).as_str() {
+                "stop" => StopProgram,
+                // File paths are case sensitive, so they're taken from the original string
+                _ => LoadProgram(original_args[1..].join(" ")),
+            },
+            "goto" => GotoSection(get_nth_arg(1)),
+            "loop" => LoopSection(get_nth_arg(1)),
+            "tap" => match get_nth_arg(1).as_str() {
+                "stop" => StopTap,
+                _ => Tap(get_nth_arg(1)),
+            },
+            "accuracy" => match get_nth_arg(1).as_str() {
+                "stop" => StopTapAlong,
+                _ => StartTapAlong,
+            },
             "timer" => {
                 let second_arg = get_nth_arg(1);
 
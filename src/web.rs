@@ -0,0 +1,553 @@
+use serde::Serialize;
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, TcpListener, TcpStream},
+    sync::{
+        Arc, Mutex, RwLock,
+        mpsc::{self, TryRecvError, TrySendError},
+    },
+    thread,
+    time::Duration,
+};
+use tungstenite::{
+    Message,
+    handshake::server::{ErrorResponse, Request, Response},
+    http::{
+        StatusCode,
+        header::{HOST, ORIGIN},
+    },
+};
+
+use metronome::{
+    BeatInfo, MetronomeData, UserInput, metronome::data::beat::accent::get_metronome_beat_accent,
+};
+
+/// The control page, which talks to the metronome over a WebSocket at `/ws`
+const CONTROL_PAGE: &str = include_str!("web/index.html");
+/// How often each WebSocket stops waiting for commands to send the latest events
+const WEB_SOCKET_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Large enough for the headers of any request a browser sends
+const REQUEST_HEAD_MAX_SIZE: usize = 8192;
+/// How long a new connection has to send its request before it's dropped
+const REQUEST_HEAD_TIMEOUT: Duration = Duration::from_secs(5);
+/// How many events can wait for a page that has stopped reading, like a tablet that
+/// went to sleep
+const WEB_SOCKET_QUEUE_SIZE: usize = 64;
+
+/// What the control page is told about, as JSON with a `type` field
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WebEvent {
+    Status {
+        tempo: i32,
+        tempo_type: String,
+        time_signature: String,
+        /// The accent of each beat of the bar, like `Xxxx`
+        accents: String,
+        subdivision: i32,
+        is_paused: bool,
+        timer: Option<String>,
+        program: Option<String>,
+    },
+    Beat {
+        beat: i32,
+        subdivided_beat: i32,
+    },
+}
+
+impl WebEvent {
+    fn status(metronome_data: &MetronomeData) -> Self {
+        let accents = (0..metronome_data.time_signature().0)
+            .map(|beat| {
+                get_metronome_beat_accent(metronome_data.beat_accents(), BeatInfo::from((beat, 0)))
+                    .to_char()
+            })
+            .collect();
+
+        Self::Status {
+            tempo: metronome_data.tempo(),
+            tempo_type: metronome_data.tempo_type().to_string(),
+            time_signature: metronome_data.time_signature().notation(),
+            accents,
+            subdivision: metronome_data.subdivision(),
+            is_paused: metronome_data.is_paused,
+            timer: metronome_data
+                .timer
+                .as_ref()
+                .map(|timer| timer.time_remaining_str()),
+            program: metronome_data
+                .program
+                .as_ref()
+                .map(|program| program.to_string()),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("web events are always valid JSON")
+    }
+}
+
+/// Whether the head of an HTTP request asks to switch to a WebSocket
+fn is_web_socket_request(request_head: &[u8]) -> bool {
+    String::from_utf8_lossy(request_head)
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .any(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("upgrade")
+                && value.trim().eq_ignore_ascii_case("websocket")
+        })
+}
+
+/// The host a request was sent to, as given in its `Host` header
+fn host_of(request_head: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(request_head)
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("host"))
+        .map(|(_, value)| value.trim().to_string())
+}
+
+/// The names the control page answers to. A website can point its own name at this
+/// computer, after which the browser sends that name with each request, so only names
+/// that no website can take over are let in
+#[derive(Debug, Clone)]
+struct AllowedHosts {
+    /// The address the page is served on
+    address: IpAddr,
+    /// The name given with `--web-host`
+    name: Option<String>,
+}
+
+impl AllowedHosts {
+    /// Whether `host`, with or without a port, is `localhost`, the address the page is
+    /// served on (any address when it's served on all of them) or the configured name
+    fn allows(&self, host: &str) -> bool {
+        let name = match host.strip_prefix('[') {
+            Some(ipv6_host) => ipv6_host.split(']').next().unwrap_or_default(),
+            None => host.split(':').next().unwrap_or_default(),
+        };
+
+        if let Ok(address) = name.parse::<IpAddr>() {
+            return self.address.is_unspecified() || address == self.address;
+        }
+
+        name.eq_ignore_ascii_case("localhost")
+            || self
+                .name
+                .as_deref()
+                .is_some_and(|allowed_name| name.eq_ignore_ascii_case(allowed_name))
+    }
+}
+
+/// Whether a WebSocket request comes from a page served here. Browsers tell where the
+/// page that opens a WebSocket is from, so any other site open in the browser is kept
+/// out. Clients outside a browser don't say, and are let in
+fn is_same_origin(request: &Request) -> bool {
+    let headers = request.headers();
+    let Some(origin) = headers.get(ORIGIN) else {
+        return true;
+    };
+    let (Ok(origin), Some(Ok(host))) =
+        (origin.to_str(), headers.get(HOST).map(|host| host.to_str()))
+    else {
+        return false;
+    };
+
+    origin
+        .split_once("://")
+        .is_some_and(|(_, origin_host)| origin_host.eq_ignore_ascii_case(host))
+}
+
+/// Turns away WebSockets opened by pages from anywhere else. The error is as large as
+/// tungstenite makes it
+#[allow(clippy::result_large_err)]
+fn check_origin(request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+    if is_same_origin(request) {
+        return Ok(response);
+    }
+
+    let mut error_response = ErrorResponse::new(Some("Forbidden".to_string()));
+    *error_response.status_mut() = StatusCode::FORBIDDEN;
+    Err(error_response)
+}
+
+/// A connection that gives back what was already read from it before the rest, so
+/// the WebSocket handshake sees the whole request
+struct ReadAgainStream {
+    already_read: io::Cursor<Vec<u8>>,
+    stream: TcpStream,
+}
+
+impl Read for ReadAgainStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.already_read.read(buf)? {
+            0 => self.stream.read(buf),
+            size => Ok(size),
+        }
+    }
+}
+
+impl Write for ReadAgainStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Reads until the blank line that ends the head of a request, which can arrive in
+/// any number of pieces. Anything after it is read too
+fn read_request_head(mut stream: impl Read) -> io::Result<Vec<u8>> {
+    let mut request_head = Vec::new();
+    let mut buffer = [0; 1024];
+
+    while !request_head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if request_head.len() > REQUEST_HEAD_MAX_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The request head is too large",
+            ));
+        }
+
+        let size = stream.read(&mut buffer)?;
+        if size == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        request_head.extend_from_slice(&buffer[..size]);
+    }
+
+    Ok(request_head)
+}
+
+/// Answers a plain HTTP request with the control page, or 404 for anything else
+fn serve_page(stream: TcpStream, request_head: &[u8]) -> io::Result<()> {
+    let request_head = String::from_utf8_lossy(request_head);
+    let request_line = request_head.lines().next().unwrap_or_default();
+
+    let (status, content_type, body) = match request_line.split_whitespace().nth(1) {
+        Some("/" | "/index.html") => ("200 OK", "text/html; charset=utf-8", CONTROL_PAGE),
+        _ => ("404 Not Found", "text/plain; charset=utf-8", "Not found"),
+    };
+
+    write_response(stream, status, content_type, body)
+}
+
+fn write_response(
+    mut stream: TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// Runs commands sent over a WebSocket, and sends it the events from `events`
+fn serve_web_socket(
+    stream: ReadAgainStream,
+    sender: mpsc::Sender<UserInput>,
+    events: mpsc::Receiver<WebEvent>,
+) -> anyhow::Result<()> {
+    let mut web_socket = tungstenite::accept_hdr(stream, check_origin)?;
+    web_socket
+        .get_ref()
+        .stream
+        .set_read_timeout(Some(WEB_SOCKET_POLL_INTERVAL))?;
+
+    loop {
+        match web_socket.read() {
+            Ok(Message::Text(command)) => {
+                if let Ok(user_input) = command.parse::<UserInput>() {
+                    sender.send(user_input)?;
+                }
+            }
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        // A page that fell behind only needs the latest beat
+        let mut latest_beat = None;
+        loop {
+            match events.try_recv() {
+                Ok(beat @ WebEvent::Beat { .. }) => latest_beat = Some(beat),
+                Ok(event) => web_socket.write(Message::text(event.to_json()))?,
+                Err(TryRecvError::Empty) => break,
+                // Too far behind to keep up, so the page reconnects and starts over
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+        if let Some(beat) = latest_beat {
+            web_socket.write(Message::text(beat.to_json()))?;
+        }
+        web_socket.flush()?;
+    }
+}
+
+/// Reads a request, and either serves the control page or upgrades to a WebSocket
+fn serve_connection(
+    mut stream: TcpStream,
+    allowed_hosts: &AllowedHosts,
+    metronome_data: &RwLock<MetronomeData>,
+    clients: &Mutex<Vec<mpsc::SyncSender<WebEvent>>>,
+    sender: mpsc::Sender<UserInput>,
+) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_HEAD_TIMEOUT))?;
+    let request_head = read_request_head(&mut stream)?;
+
+    if !host_of(&request_head).is_some_and(|host| allowed_hosts.allows(&host)) {
+        let content_type = "text/plain; charset=utf-8";
+        return Ok(write_response(
+            stream,
+            "403 Forbidden",
+            content_type,
+            "Forbidden",
+        )?);
+    }
+
+    if !is_web_socket_request(&request_head) {
+        return Ok(serve_page(stream, &request_head)?);
+    }
+
+    // A new page starts out with the current settings
+    let (events_sender, events) = mpsc::sync_channel(WEB_SOCKET_QUEUE_SIZE);
+    let status = WebEvent::status(&metronome_data.read().unwrap());
+    let _ = events_sender.try_send(status);
+    clients.lock().unwrap().push(events_sender);
+
+    let stream = ReadAgainStream {
+        already_read: io::Cursor::new(request_head),
+        stream,
+    };
+    serve_web_socket(stream, sender, events)
+}
+
+/// Queues `event` for every page. Beats a page has no room for are skipped, while a
+/// page that has no room for new settings is dropped, and reconnects to get them.
+/// Pages that were closed are forgotten
+fn send_to_clients(clients: &mut Vec<mpsc::SyncSender<WebEvent>>, event: &WebEvent) {
+    clients.retain(|client| match client.try_send(event.clone()) {
+        Ok(()) | Err(TrySendError::Full(WebEvent::Beat { .. })) => true,
+        Err(_) => false,
+    });
+}
+
+/// Serves a control page over HTTP, with the settings and the beats pushed to it over
+/// a WebSocket. Commands from the page go through the same channel as typed ones, so
+/// the terminal stays in sync
+pub struct WebServer {
+    metronome_data: Arc<RwLock<MetronomeData>>,
+    /// Where the events of each connected page go
+    clients: Arc<Mutex<Vec<mpsc::SyncSender<WebEvent>>>>,
+}
+
+impl WebServer {
+    pub fn start(
+        address: IpAddr,
+        port: u16,
+        host_name: Option<String>,
+        metronome_data: Arc<RwLock<MetronomeData>>,
+        sender: mpsc::Sender<UserInput>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind((address, port))?;
+        let allowed_hosts = AllowedHosts {
+            address,
+            name: host_name,
+        };
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let accepting_metronome_data = Arc::clone(&metronome_data);
+        let accepting_clients = Arc::clone(&clients);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+
+                let allowed_hosts = allowed_hosts.clone();
+                let metronome_data = Arc::clone(&accepting_metronome_data);
+                let clients = Arc::clone(&accepting_clients);
+                let sender = sender.clone();
+
+                // Nothing is read until the connection has its own thread, so one that
+                // never sends anything doesn't hold up the others
+                thread::spawn(move || {
+                    serve_connection(stream, &allowed_hosts, &metronome_data, &clients, sender)
+                });
+            }
+        });
+
+        Ok(Self {
+            metronome_data,
+            clients,
+        })
+    }
+
+    fn send_event(&self, event: WebEvent) {
+        send_to_clients(&mut self.clients.lock().unwrap(), &event);
+    }
+
+    pub fn send_status(&self) {
+        let status = WebEvent::status(&self.metronome_data.read().unwrap());
+        self.send_event(status);
+    }
+
+    pub fn send_beat(&self, beat_info: BeatInfo) {
+        self.send_event(WebEvent::Beat {
+            beat: beat_info.current_beat,
+            subdivided_beat: beat_info.subdivided_beat,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web::{
+        AllowedHosts, WebEvent, host_of, is_same_origin, is_web_socket_request, read_request_head,
+        send_to_clients,
+    };
+    use clap::Parser;
+    use metronome::{MetronomeData, cli::Cli};
+    use std::{
+        io::{self, Read},
+        net::{IpAddr, Ipv4Addr},
+        sync::mpsc,
+    };
+    use tungstenite::handshake::server::Request;
+
+    #[test]
+    fn status_is_sent_as_json() {
+        let cli = Cli::parse_from(["metronome", "90", "3/4", "-s", "2"]);
+        let metronome_data = MetronomeData::new(&cli.metronome_args).unwrap();
+        let status = WebEvent::status(&metronome_data).to_json();
+
+        assert!(status.starts_with(r#"{"type":"status","tempo":90,"#));
+        assert!(status.contains(r#""time_signature":"3/4","accents":"Xxx","subdivision":2,"#));
+        assert!(status.ends_with(r#""timer":null,"program":null}"#));
+        assert_eq!(
+            WebEvent::Beat {
+                beat: 2,
+                subdivided_beat: 1
+            }
+            .to_json(),
+            r#"{"type":"beat","beat":2,"subdivided_beat":1}"#
+        );
+    }
+
+    #[test]
+    fn pages_that_stop_reading_dont_pile_up_events() {
+        let cli = Cli::parse_from(["metronome"]);
+        let status = WebEvent::status(&MetronomeData::new(&cli.metronome_args).unwrap());
+        let beat = WebEvent::Beat {
+            beat: 0,
+            subdivided_beat: 0,
+        };
+
+        let (client, events) = mpsc::sync_channel(2);
+        let mut clients = vec![client];
+
+        // Beats that don't fit are skipped, and the page is kept
+        for _ in 0..5 {
+            send_to_clients(&mut clients, &beat);
+        }
+        assert_eq!(clients.len(), 1);
+        assert_eq!(events.try_iter().count(), 2);
+
+        // New settings that don't fit drop the page, which then reconnects
+        for _ in 0..3 {
+            send_to_clients(&mut clients, &status);
+        }
+        assert!(clients.is_empty());
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [status.clone(), status]
+        );
+    }
+
+    #[test]
+    fn web_sockets_from_other_sites_are_turned_away() {
+        let request = |origin: Option<&str>| {
+            let mut request = Request::builder().header("Host", "127.0.0.1:8080");
+            if let Some(origin) = origin {
+                request = request.header("Origin", origin);
+            }
+            request.body(()).unwrap()
+        };
+
+        assert!(is_same_origin(&request(Some("http://127.0.0.1:8080"))));
+        assert!(is_same_origin(&request(None)));
+        assert!(!is_same_origin(&request(Some("https://example.com"))));
+        assert!(!is_same_origin(&request(Some("http://127.0.0.1:9000"))));
+        assert!(!is_same_origin(&request(Some("null"))));
+    }
+
+    #[test]
+    fn only_names_that_cant_be_rebound_are_served() {
+        let local = AllowedHosts {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            name: None,
+        };
+        assert!(local.allows("localhost:8080"));
+        assert!(local.allows("127.0.0.1:8080"));
+        assert!(local.allows("LOCALHOST"));
+        assert!(!local.allows("192.168.1.20:8080"));
+        assert!(!local.allows("evil.example.com:8080"));
+
+        let network = AllowedHosts {
+            address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            name: Some("studio-mac.local".to_string()),
+        };
+        assert!(network.allows("192.168.1.20:8080"));
+        assert!(network.allows("[::1]:8080"));
+        assert!(network.allows("studio-mac.local:8080"));
+        assert!(!network.allows("evil.example.com:8080"));
+
+        assert_eq!(
+            host_of(b"GET / HTTP/1.1\r\nHost: localhost:8080\r\n\r\n").as_deref(),
+            Some("localhost:8080")
+        );
+        assert_eq!(host_of(b"GET / HTTP/1.1\r\n\r\n"), None);
+    }
+
+    #[test]
+    fn request_head_is_read_in_pieces() {
+        let pieces = ["GET /ws HTTP/1.1\r\nUpgr", "ade: websocket\r\n", "\r\n"];
+        let reader = pieces
+            .iter()
+            .map(|piece| piece.as_bytes())
+            .fold(Box::new(io::empty()) as Box<dyn Read>, |reader, piece| {
+                Box::new(reader.chain(piece))
+            });
+
+        let request_head = read_request_head(reader).unwrap();
+        assert_eq!(request_head, pieces.concat().as_bytes());
+        assert!(is_web_socket_request(&request_head));
+
+        let cut_off = read_request_head("GET / HTTP/1.1\r\nHost: localhost\r\n".as_bytes());
+        assert!(cut_off.is_err());
+    }
+
+    #[test]
+    fn web_socket_requests_are_told_apart() {
+        assert!(is_web_socket_request(
+            b"GET /ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: WebSocket\r\nConnection: Upgrade\r\n\r\n"
+        ));
+        assert!(!is_web_socket_request(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n"
+        ));
+    }
+}